@@ -0,0 +1,290 @@
+use std::collections::HashSet;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::car::heading;
+use crate::layout::Point;
+
+// pictures a plane is drawn with, one is picked for each
+pub const PLANE_SPRITES: [&str; 2] = ["assets/Blemheim.png", "assets/Hawker.png"];
+// ground speed in px/s, planes slow down to no less than MIN_SPEED to keep their distance
+pub const CRUISE_SPEED: f64 = 360.0;
+const MIN_SPEED: f64 = 240.0;
+const SPEED_CHANGE: f64 = 80.0;
+// feet per second up or down, degrees per second of turn, quick for the small world
+const CLIMB_RATE: f64 = 500.0;
+const TURN_RATE: f64 = 40.0;
+// altitudes planes cruise at, in feet
+pub const FLIGHT_LEVELS: [f64; 4] = [3000.0, 4000.0, 5000.0, 6000.0];
+// two planes closer than this across the ground and in height have lost separation
+pub const HORIZONTAL_SEPARATION: f64 = 200.0;
+pub const VERTICAL_SEPARATION: f64 = 1000.0;
+// how far ahead in seconds a plane looks for others it would come too close to
+const LOOKAHEAD: f64 = 4.0;
+const LOOKAHEAD_STEPS: usize = 16;
+// a plane passes a fix once it is this close
+const FIX_RADIUS: f64 = 40.0;
+// planes fly out this far past the edge of the world before they are gone
+const MARGIN: f64 = 100.0;
+
+// fixes a plane flies over one after the other and the flight level it is given
+#[derive(Debug, Clone)]
+pub struct FlightPath {
+    pub fixes: Vec<Point>,
+    pub level: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Plane {
+    pub id: usize,
+    pub position: Point,
+    // sprite angle, 0 is up the screen like the cars
+    pub heading: f64,
+    pub speed: f64,
+    // feet above the ground, and what the plane climbs or descends to
+    pub altitude: f64,
+    pub level: f64,
+    pub fixes: Vec<Point>,
+    // the fix it passed last, or where it came in, the leg to the next fix starts here
+    pub from: Point,
+    pub sprite: usize,
+}
+
+impl Plane {
+    // Flies one step: turns towards the next fix no faster than TURN_RATE, changes
+    // height towards its level and speed towards the target speed. A fix is passed once
+    // the plane is close to it or beyond the line through it square to the leg, so one
+    // that turns too wide to hit it flies on instead of circling it.
+    fn fly(&mut self, target_speed: f64, dt: f64) {
+        if let Some(&(x, y)) = self.fixes.first() {
+            let wanted = heading(x - self.position.0, y - self.position.1);
+            let turn = (wanted - self.heading + 180.0).rem_euclid(360.0) - 180.0;
+            let max = TURN_RATE * dt;
+            self.heading = (self.heading + turn.clamp(-max, max)).rem_euclid(360.0);
+        }
+        let climb = CLIMB_RATE * dt;
+        self.altitude += (self.level - self.altitude).clamp(-climb, climb);
+        let change = SPEED_CHANGE * dt;
+        self.speed += (target_speed - self.speed).clamp(-change, change);
+
+        let (sin, cos) = self.heading.to_radians().sin_cos();
+        self.position.0 += sin * self.speed * dt;
+        self.position.1 -= cos * self.speed * dt;
+        if let Some(&fix) = self.fixes.first() {
+            let beyond = (self.position.0 - fix.0) * (fix.0 - self.from.0)
+                + (self.position.1 - fix.1) * (fix.1 - self.from.1)
+                > 0.0;
+            if beyond || distance(self.position, fix) < FIX_RADIUS {
+                self.from = self.fixes.remove(0);
+            }
+        }
+    }
+
+    // where the plane is after t seconds more on its heading, and its altitude then
+    fn ahead(&self, t: f64) -> (Point, f64) {
+        let (sin, cos) = self.heading.to_radians().sin_cos();
+        let climb = CLIMB_RATE * t;
+        (
+            (
+                self.position.0 + sin * self.speed * t,
+                self.position.1 - cos * self.speed * t,
+            ),
+            self.altitude + (self.level - self.altitude).clamp(-climb, climb),
+        )
+    }
+}
+
+// Air traffic over the intersections, a layer of its own the road never sees. Planes
+// fly flight paths across the world at assigned flight levels. Each tick every plane
+// looks ahead for one it would come within the separation minima of, and the later of
+// the two gives way: it climbs or descends to the nearest level that keeps it clear of
+// every earlier plane, or slows down if there is none. Later planes in turn give way
+// to it. Separation that is lost all the same is counted.
+pub struct Airspace {
+    pub planes: Vec<Plane>,
+    pub paths: Vec<FlightPath>,
+    // planes generated per minute on top of those spawned by hand
+    pub rate: f64,
+    pub spawned: usize,
+    // planes that flew out of the world again
+    pub flown: usize,
+    // times a plane was given another level to stay clear of one
+    pub level_changes: usize,
+    pub separation_losses: usize,
+    bounds: (f64, f64),
+    losing: HashSet<(usize, usize)>,
+    // planes draw from a generator of their own, so the road traffic of a seed is the
+    // same whatever flies over it
+    rng: StdRng,
+}
+
+impl Airspace {
+    pub fn new(width: f64, height: f64, seed: u64) -> Self {
+        Airspace {
+            planes: Vec::new(),
+            paths: flight_paths(width, height),
+            rate: 0.0,
+            spawned: 0,
+            flown: 0,
+            level_changes: 0,
+            separation_losses: 0,
+            bounds: (width, height),
+            losing: HashSet::new(),
+            rng: StdRng::seed_from_u64(seed.wrapping_add(1)),
+        }
+    }
+
+    // the world the flight paths are laid over
+    pub fn set_bounds(&mut self, width: f64, height: f64) {
+        self.bounds = (width, height);
+        self.paths = flight_paths(width, height);
+    }
+
+    pub fn tick(&mut self, dt: f64) {
+        if self.rate > 0.0 && self.rng.gen_bool((self.rate / 60.0 * dt).min(1.0)) {
+            self.spawn();
+        }
+
+        // earlier planes keep their level, later ones move out of their way
+        let mut slow = HashSet::new();
+        for i in 0..self.planes.len() {
+            if !(0..i).any(|j| conflict(&self.planes[i], &self.planes[j])) {
+                continue;
+            }
+            let plane = &self.planes[i];
+            let clear = |level: f64| {
+                let moved = Plane {
+                    level,
+                    ..plane.clone()
+                };
+                (0..i).all(|j| !conflict(&moved, &self.planes[j]))
+            };
+            let mut levels = FLIGHT_LEVELS;
+            levels.sort_by(|a, b| (a - plane.level).abs().total_cmp(&(b - plane.level).abs()));
+            match levels.into_iter().find(|&l| l != plane.level && clear(l)) {
+                Some(level) => {
+                    self.planes[i].level = level;
+                    self.level_changes += 1;
+                }
+                None => {
+                    slow.insert(self.planes[i].id);
+                }
+            }
+        }
+        for plane in &mut self.planes {
+            let target = if slow.contains(&plane.id) {
+                MIN_SPEED
+            } else {
+                CRUISE_SPEED
+            };
+            plane.fly(target, dt);
+        }
+
+        let mut losing = HashSet::new();
+        for (i, a) in self.planes.iter().enumerate() {
+            for b in &self.planes[i + 1..] {
+                if distance(a.position, b.position) < HORIZONTAL_SEPARATION
+                    && (a.altitude - b.altitude).abs() < VERTICAL_SEPARATION
+                {
+                    let ids = (a.id.min(b.id), a.id.max(b.id));
+                    if !self.losing.contains(&ids) {
+                        self.separation_losses += 1;
+                    }
+                    losing.insert(ids);
+                }
+            }
+        }
+        self.losing = losing;
+
+        let (width, height) = self.bounds;
+        let before = self.planes.len();
+        self.planes.retain(|p| {
+            (-MARGIN..width + MARGIN).contains(&p.position.0)
+                && (-MARGIN..height + MARGIN).contains(&p.position.1)
+        });
+        self.flown += before - self.planes.len();
+    }
+
+    // A plane on a random flight path, entering at the level of the path or the nearest
+    // one where it is clear of every plane already flying. There is no plane if none is.
+    pub fn spawn(&mut self) -> bool {
+        if self.paths.is_empty() {
+            return false;
+        }
+        let path = &self.paths[self.rng.gen_range(0..self.paths.len())];
+        let (start, next) = (path.fixes[0], path.fixes[1]);
+        let mut plane = Plane {
+            id: self.spawned,
+            position: start,
+            heading: heading(next.0 - start.0, next.1 - start.1),
+            speed: CRUISE_SPEED,
+            altitude: path.level,
+            level: path.level,
+            fixes: path.fixes[1..].to_vec(),
+            from: start,
+            sprite: self.rng.gen_range(0..PLANE_SPRITES.len()),
+        };
+        let mut levels = FLIGHT_LEVELS;
+        levels.sort_by(|a, b| (a - path.level).abs().total_cmp(&(b - path.level).abs()));
+        let clear = levels.into_iter().find(|&level| {
+            plane.altitude = level;
+            plane.level = level;
+            self.planes.iter().all(|p| !conflict(&plane, p))
+        });
+        if clear.is_none() {
+            return false;
+        }
+        self.planes.push(plane);
+        self.spawned += 1;
+        true
+    }
+}
+
+// Flying on as they are, straight ahead and towards their levels, the two are closer
+// than both separations at once at some point within the lookahead.
+fn conflict(a: &Plane, b: &Plane) -> bool {
+    (0..=LOOKAHEAD_STEPS).any(|k| {
+        let t = LOOKAHEAD * k as f64 / LOOKAHEAD_STEPS as f64;
+        let (pa, pb) = (a.ahead(t), b.ahead(t));
+        distance(pa.0, pb.0) < HORIZONTAL_SEPARATION && (pa.1 - pb.1).abs() < VERTICAL_SEPARATION
+    })
+}
+
+// Routes across a world of that size: the old diagonal the planes took, and paths
+// crossing it and each other at a few levels, so planes do meet and are kept apart.
+fn flight_paths(width: f64, height: f64) -> Vec<FlightPath> {
+    let (w, h) = (width, height);
+    let path = |fixes: &[(f64, f64)], level: f64| FlightPath {
+        fixes: fixes.iter().map(|&(x, y)| (x * w, y * h)).collect(),
+        level,
+    };
+    // half the margin out past the edge on either axis
+    let (ox, oy) = (MARGIN / 2.0 / w, MARGIN / 2.0 / h);
+    vec![
+        path(
+            &[(1.0 + ox, 0.83), (0.5, 0.5), (-ox, 0.14)],
+            FLIGHT_LEVELS[1],
+        ),
+        path(
+            &[(-ox, 0.25), (0.4, 0.3), (0.7, 0.2), (1.0 + ox, 0.3)],
+            FLIGHT_LEVELS[1],
+        ),
+        path(
+            &[(0.3, -oy), (0.35, 0.5), (0.25, 1.0 + oy)],
+            FLIGHT_LEVELS[0],
+        ),
+        path(
+            &[(-ox, 1.0 + oy), (0.6, 0.45), (1.0 + ox, -oy)],
+            FLIGHT_LEVELS[2],
+        ),
+        path(
+            &[(0.8, 1.0 + oy), (0.75, 0.4), (0.85, -oy)],
+            FLIGHT_LEVELS[3],
+        ),
+    ]
+}
+
+fn distance(a: Point, b: Point) -> f64 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
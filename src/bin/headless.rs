@@ -0,0 +1,193 @@
+use smart_road::cli::Options;
+use smart_road::simulation::{DT, Simulation};
+use smart_road::trip::{Trip, direction_name, movement_name};
+use smart_road::vehicle::VehicleType;
+
+// Runs the intersection without a window, for CI and batch runs.
+//   cargo run --bin headless --no-default-features -- --seconds 60 --seed 42 --policy reservation --headway 1.2
+fn main() {
+    let options = Options::from_args();
+    let seconds = options.seconds;
+
+    let mut sim = Simulation::new(options.seed.unwrap_or(0), &options.policy);
+    sim.following = options.following;
+    sim.turning = options.turning;
+    sim.set_network(options.network.clone());
+    sim.set_turn_radii(options.turn_radii);
+    sim.pause_on_collision = options.pause_on_collision;
+    sim.preemption = options.preemption;
+    sim.air.rate = options.planes;
+    sim.pedestrian_rate = options.pedestrians;
+    sim.cyclists = options.cyclists;
+    sim.humans = options.humans;
+    sim.set_schedule(options.schedule.clone());
+    sim.set_demand(options.demand.clone());
+    if options.random_traffic.unwrap_or(true) {
+        sim.start_auto_spawn();
+    }
+
+    let ticks = (seconds / DT).round() as u64;
+    for _ in 0..ticks {
+        sim.tick();
+        if sim.paused {
+            break;
+        }
+    }
+
+    let stats = &sim.stats;
+    println!("Policy: {}", sim.policy_name());
+    println!("Seed: {}", sim.seed);
+    println!("Simulated Time: {:.2}s", sim.time);
+    println!("Total Cars Spawned: {}", stats.spawned);
+    println!("Finished: {}", stats.finished().count());
+    println!("Max Time: {:.2}s", stats.max_duration());
+    println!("Min Time: {:.2}s", stats.min_duration().unwrap_or_default());
+    println!(
+        "Throughput: {:.1} cars/min",
+        stats.finished().count() as f64 / sim.time * 60.0
+    );
+    let seconds = |s: Option<f64>| s.map_or("-".to_string(), |s| format!("{:.2}s", s));
+    println!(
+        "Delay: mean {}, median {}, 95th percentile {}",
+        seconds(stats.mean_delay_of(|_| true)),
+        seconds(stats.delay_percentile(50.0)),
+        seconds(stats.delay_percentile(95.0))
+    );
+    println!(
+        "Speed: mean {:.0} px/s, max {:.0} px/s",
+        stats.mean_speed().unwrap_or_default(),
+        stats.max_speed()
+    );
+    let (stops, stopped) = stats.mean_stops().unwrap_or_default();
+    println!(
+        "Stops: {:.2} per vehicle, {:.2}s stopped on average",
+        stops, stopped
+    );
+    // a line per approach with the movements taken from it, in cars a minute
+    println!("Throughput by Approach:");
+    let per_minute = |n: usize| n as f64 / sim.time * 60.0;
+    let throughput = stats.throughput();
+    for group in throughput.chunk_by(|a, b| (a.0.node, a.0.from) == (b.0.node, b.0.from)) {
+        let hop = group[0].0;
+        let node = if sim.network.nodes.len() > 1 {
+            format!("node {} ", hop.node)
+        } else {
+            String::new()
+        };
+        let movements: Vec<String> = group
+            .iter()
+            .map(|(hop, n)| format!("{} {:.1}", movement_name(hop.movement), per_minute(*n)))
+            .collect();
+        println!(
+            "  {}{}: {:.1} cars/min ({})",
+            node,
+            direction_name(hop.from),
+            per_minute(group.iter().map(|(_, n)| n).sum()),
+            movements.join(", ")
+        );
+    }
+    println!(
+        "Entry Queue: {} cars waited, max {} per lane, mean {:.2} cars",
+        stats.queued,
+        stats.max_queue,
+        stats.mean_queue(sim.time)
+    );
+    let delay = |emergency| match stats.mean_delay(emergency) {
+        Some(d) => format!("{:.2}s", d),
+        None => "-".to_string(),
+    };
+    println!(
+        "Emergency Vehicles: {} finished, mean delay {} vs {} for other traffic{}",
+        stats.count(|trip| trip.vehicle.is_emergency()),
+        delay(true),
+        delay(false),
+        if sim.preemption {
+            ""
+        } else {
+            " (no preemption)"
+        }
+    );
+    let cyclists = stats.count(|trip| trip.vehicle == VehicleType::Bicycle);
+    if cyclists > 0 {
+        let delay = |cyclists| match stats.mean_cyclist_delay(cyclists) {
+            Some(d) => format!("{:.2}s", d),
+            None => "-".to_string(),
+        };
+        println!(
+            "Cyclists: {} finished, mean delay {} vs {} for motor traffic",
+            cyclists,
+            delay(true),
+            delay(false)
+        );
+    }
+    let humans = stats.count(|trip| trip.human);
+    if humans > 0 {
+        let delay = |humans| match stats.mean_human_delay(humans) {
+            Some(d) => format!("{:.2}s", d),
+            None => "-".to_string(),
+        };
+        println!(
+            "Human Drivers: {} finished, mean delay {} vs {} for automated vehicles",
+            humans,
+            delay(true),
+            delay(false)
+        );
+    }
+    let air = &sim.air;
+    if air.spawned > 0 {
+        println!(
+            "Aircraft: {} spawned, {} flown through, {} level changes, {} separation losses",
+            air.spawned, air.flown, air.level_changes, air.separation_losses
+        );
+    }
+    if stats.pedestrians > 0 {
+        println!(
+            "Pedestrians: {} arrived, {} crossed, mean wait {}, max wait {:.2}s",
+            stats.pedestrians,
+            stats.crossed,
+            stats
+                .mean_pedestrian_wait()
+                .map_or("-".to_string(), |w| format!("{:.2}s", w)),
+            stats.max_pedestrian_wait()
+        );
+    }
+    println!("Close Calls: {}", stats.close_calls);
+    println!("Collisions: {}", stats.collisions.len());
+    for c in &stats.collisions {
+        println!(
+            "  {:.2}s cars {} and {} at ({:.0}, {:.0})",
+            c.time, c.ids.0, c.ids.1, c.position.0, c.position.1
+        );
+    }
+    if !stats.pedestrian_collisions.is_empty() {
+        println!(
+            "Pedestrian Collisions: {}",
+            stats.pedestrian_collisions.len()
+        );
+        for c in &stats.pedestrian_collisions {
+            println!(
+                "  {:.2}s car {} and pedestrian {} at ({:.0}, {:.0})",
+                c.time, c.ids.0, c.ids.1, c.position.0, c.position.1
+            );
+        }
+    }
+
+    if let Some(path) = &options.trips {
+        let mut trips: Vec<&Trip> = stats.trips.values().collect();
+        trips.sort_by_key(|trip| trip.id);
+        let mut table = format!("{}\n", Trip::CSV_HEADER);
+        for trip in trips {
+            table += &trip.csv_row();
+            table.push('\n');
+        }
+        if let Err(e) = std::fs::write(path, table) {
+            eprintln!("cannot write {}: {}", path, e);
+            std::process::exit(2);
+        }
+    }
+
+    // a run stopped by a collision fails, so CI catches policy regressions
+    if sim.paused {
+        std::process::exit(1);
+    }
+}
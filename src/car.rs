@@ -1,17 +1,56 @@
-use sdl2::rect::Rect;
-use sdl2::render::{Canvas, Texture};
-use sdl2::video::Window;
+use std::f64::consts::TAU;
 use std::ops::RangeInclusive;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum Lane {
+use serde::Deserialize;
+
+use crate::following::Idm;
+use crate::layout::{LANE_CHANGE_LENGTH, MERGE_LOOKAHEAD, Point, right_of};
+use crate::network::Hop;
+use crate::policy::{APPROACH_DISTANCE, Access, REACTION_TIME};
+use crate::vehicle::VehicleType;
+
+// speeds are in px per simulated second
+pub const ROAD_SPEED: f64 = 300.0;
+pub const INTERSECTION_SPEED: f64 = 480.0;
+pub const RING_SPEED: f64 = 200.0;
+// px/s², comfortable values for a car, other vehicles have their own
+pub const MAX_ACCEL: f64 = 300.0;
+pub const MAX_DECEL: f64 = 600.0;
+// braking is never harder than this, even when the model asks for it
+pub const EMERGENCY_DECEL: f64 = 2.0 * MAX_DECEL;
+// body of a car, the box and the conflict table leave room for one
+pub const VEHICLE_LENGTH: f64 = 50.0;
+pub const VEHICLE_WIDTH: f64 = 30.0;
+// a car held at the box stops with its centre this far before the edge
+pub const STOP_LINE_OFFSET: f64 = 40.0;
+// how far round the ring a car looks for the car it follows
+const RING_LOOKAHEAD: f64 = 200.0;
+// room to the side one vehicle needs to pass another in the same lane, a cyclist
+// riding at the kerb can only be passed in a wide lane
+pub const PASSING_GAP: f64 = 12.0;
+
+// x and y range of a rectangle, like the box where the routes cross
+pub type Area = (RangeInclusive<f64>, RangeInclusive<f64>);
+
+// what a car does at the intersection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Movement {
+    Left,
     Straight,
     Right,
-    Left,
-    Air,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+// inbound lane `index` of the arm, counted from the centre line, and the movement
+// the car takes from it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Lane {
+    pub index: usize,
+    pub movement: Movement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Direction {
     North,
     South,
@@ -27,21 +66,50 @@ pub struct Waypoint {
 }
 
 #[derive(Clone)]
-pub struct Car<'a> {
+pub struct Car {
     pub id: usize,
     pub position: (f64, f64),
     pub speed: f64,
+    pub target_speed: f64,
+    pub max_accel: f64,
+    pub max_decel: f64,
+    pub idm: Idm,
     pub waypoints: Vec<Waypoint>,
     pub lane: Lane,
     pub direction: Direction,
-    pub texture: &'a Texture<'a>,
+    // the box of the layout the car drives through
+    pub intersection: Area,
+    // (lane it comes from, distance before the box the pocket opens) for a car
+    // bound for a turn pocket
+    pub pocket: Option<(usize, f64)>,
+    // unit vector the car drives along on its approach, up to the first waypoint
+    pub approach: Point,
+    // (centre, radius) of the roundabout the car drives round, if there is one
+    pub ring: Option<(Point, f64)>,
+    // intersection of the network the car is driving through and the ones still to come
+    pub node: usize,
+    pub trip: Vec<Hop>,
+    pub vehicle: VehicleType,
+    // px right of the middle of its lane it rides at, the path is moved over by as much
+    pub side: f64,
+    // driven by a human who never talks to the manager, see policy::human_access
+    pub human: bool,
+    // seconds the human driver has seen a gap to cross in, and seconds it has stood
+    // with the way in front of it clear
+    pub gap_seen: f64,
+    pub standing: f64,
+    pub sprite: usize,
     pub angle: f64,
     pub is_waiting: bool,
-    pub size: Option<(u32, u32)>,
     pub close_call_triggered: bool,
+    // seconds lost so far against driving the whole way at cruise speed
+    pub delay: f64,
+    // seconds since it came within APPROACH_DISTANCE of the box it drives up to
+    pub approaching: f64,
 }
 
-impl<'a> Car<'a> {
+impl Car {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         lane: Lane,
         start: (f64, f64),
@@ -49,204 +117,544 @@ impl<'a> Car<'a> {
         speed: f64,
         id: usize,
         direction: Direction,
-        texture: &'a Texture<'a>,
-        size: Option<(u32, u32)>,
+        intersection: Area,
+        sprite: usize,
     ) -> Self {
+        let approach = approach_along(start, &waypoints);
         Car {
             id,
             position: start,
             speed,
-            // max_reached_speed: speed,
+            target_speed: speed,
+            max_accel: MAX_ACCEL,
+            max_decel: MAX_DECEL,
+            idm: Idm::default(),
             waypoints,
             lane,
             direction,
-            texture,
-            angle: match (direction, lane) {
-                (_, Lane::Air) => 310.0,
-                (Direction::South, _) => 360.0,
-                (Direction::North, _) => 180.0,
-                (Direction::East, _) => 270.0,
-                (Direction::West, _) => 90.0,
-            },
+            intersection,
+            pocket: None,
+            approach,
+            ring: None,
+            node: 0,
+            trip: Vec::new(),
+            vehicle: VehicleType::Car,
+            side: 0.0,
+            human: false,
+            gap_seen: 0.0,
+            standing: 0.0,
+            sprite,
+            angle: heading(approach.0, approach.1),
             is_waiting: false,
-            size,
             close_call_triggered: false,
+            delay: 0.0,
+            approaching: 0.0,
         }
     }
 
-    pub fn update_position(&mut self, others: &[Car], close_call_counter: &mut usize) {
-        if self.lane == Lane::Right || self.lane == Lane::Air {
-            self.is_waiting = false;
-        } else if self.is_car_in_front(others, 60.0) {
-            self.speed = 0.0;
-            self.is_waiting = true;
+    // a road vehicle of that kind, with its sprite and dynamics
+    pub fn set_vehicle(&mut self, vehicle: VehicleType) {
+        let spec = vehicle.spec();
+        self.vehicle = vehicle;
+        self.sprite = vehicle.sprite();
+        self.max_accel = spec.max_accel;
+        self.max_decel = spec.max_decel;
+    }
 
-            if !self.close_call_triggered {
-                *close_call_counter += 1;
-                self.close_call_triggered = true;
-            }
+    pub fn length(&self) -> f64 {
+        self.vehicle.spec().length
+    }
+
+    pub fn width(&self) -> f64 {
+        self.vehicle.spec().width
+    }
+
+    pub fn is_emergency(&self) -> bool {
+        self.vehicle.is_emergency()
+    }
 
-            return;
+    // Drives on from where it is along the route through another intersection. The
+    // approach is the way the arm runs, the route may start with a move over to
+    // another lane.
+    pub fn set_route(
+        &mut self,
+        lane: Lane,
+        direction: Direction,
+        approach: Point,
+        intersection: Area,
+        waypoints: Vec<Waypoint>,
+    ) {
+        self.approach = approach;
+        self.lane = lane;
+        self.direction = direction;
+        self.intersection = intersection;
+        self.waypoints = waypoints;
+    }
+
+    pub fn update_position(
+        &mut self,
+        others: &[Car],
+        access: Access,
+        close_call_counter: &mut usize,
+        dt: f64,
+    ) {
+        // (gap, speed) of the closest thing the car has to stay behind
+        let mut leader: Option<(f64, f64)> = None;
+
+        if let Some(front) = self.car_in_front(others) {
+            let gap = self.gap_to(front.position, front.length());
+            if gap < self.idm.min_gap * 0.5 {
+                if !self.close_call_triggered {
+                    *close_call_counter += 1;
+                    self.close_call_triggered = true;
+                }
+            } else {
+                self.close_call_triggered = false;
+            }
+            leader = Some((gap, front.speed));
         } else {
             self.close_call_triggered = false;
         }
 
-        let bounds = (600.0..=1000.0, 400.0..=800.0);
-        // check if the car close to intsersection مفرق
-        let inside_intersection = bounds.0.contains(&self.position.0) && bounds.1.contains(&self.position.1);
+        // the intersection policy decides if the car may go on and how fast
+        let target = match access {
+            Access::Go(speed) => speed,
+            Access::Stop => match self.distance_to_stop_line() {
+                Some(line) => {
+                    if leader.is_none_or(|(gap, _)| line < gap) {
+                        leader = Some((line, 0.0));
+                    }
+                    self.cruise_speed()
+                }
+                // told to stop inside the box, brake as hard as allowed
+                None => 0.0,
+            },
+        };
 
-        self.speed = match self.lane {
-            Lane::Air => 10.5,
-            _ if inside_intersection => 8.0,
-            _ => 5.0,
+        self.drive(target, leader, dt);
+        self.is_waiting = self.speed < 1.0;
+        self.delay += (1.0 - self.speed / self.cruise_speed()).max(0.0) * dt;
+        let approaches = self.distance_to_box() <= APPROACH_DISTANCE
+            && !self.occupies_box()
+            && !self.has_left_box();
+        self.approaching = if approaches {
+            self.approaching + dt
+        } else {
+            0.0
         };
-        
-        if inside_intersection && self.lane != Lane::Right && self.lane != Lane::Air {
-            for c in others {
-                if c.id != self.id
-                    && c.in_bounds(&bounds)
-                    && c.id < self.id
-                    && self.conflicts_with(c)
-                {
-                    self.speed = 0.0;
-                    self.is_waiting = true;
-                    return;
-                }
+    }
+
+    // Moves the speed towards target with the car following model and then moves along
+    // the path. Braking stays within EMERGENCY_DECEL, a car that cannot stop in time runs
+    // into its leader and the collision checks report it. A human driver standing still
+    // sets off only once the way has been clear for its reaction time; it brakes as soon
+    // as the model says so, like everyone else.
+    pub fn drive(&mut self, target: f64, leader: Option<(f64, f64)>, dt: f64) {
+        self.target_speed = target;
+        let mut accel = self
+            .idm
+            .acceleration(self.speed, target, self.max_accel, self.max_decel, leader)
+            .max(-EMERGENCY_DECEL);
+        if self.human {
+            let waits = self.speed < 1.0 && accel > 0.0;
+            self.standing = if waits { self.standing + dt } else { 0.0 };
+            if waits && self.standing < REACTION_TIME {
+                accel = 0.0;
             }
-            self.is_waiting = false;
-        } else if self.is_waiting {
-            //check if the car can move if there not a car in the ins_area
-            let mut allowed = true;
-            for c in others {
-                if c.id != self.id && c.in_bounds(&bounds) && c.id < self.id {
-                    allowed = false;
-                    break;
-                }
+        }
+
+        self.speed = (self.speed + accel * dt).max(0.0);
+        self.advance(dt);
+    }
+
+    pub fn stopping_distance(&self) -> f64 {
+        self.speed * self.speed / (2.0 * self.max_decel)
+    }
+
+    // update pos of car along its waypoints with the current speed,
+    // turning the sprite along the path as it goes
+    pub fn advance(&mut self, dt: f64) {
+        let mut step = self.speed * dt;
+        while step > 0.0
+            && let Some(target) = self.waypoints.first().copied()
+        {
+            let dx = target.x - self.position.0;
+            let dy = target.y - self.position.1;
+            let dist = (dx * dx + dy * dy).sqrt();
+
+            if dist > 0.0 {
+                self.angle = heading(dx, dy);
             }
-            if allowed && !self.is_car_in_front(others, 60.0) {
-                for c in others {
-                    if c.id != self.id &&
-                        c.in_bounds(&bounds) &&
-                        // && c.id < self.id  -> حاسس انه صار احسن بدونها جرب يا ذكي
-                        self.conflicts_with(c)
-                    {
-                        self.speed = 0.0;
-                        self.is_waiting = true;
-                        return;
-                    }
+            if dist <= step {
+                self.position = (target.x, target.y);
+                if let Some(angle) = target.angle {
+                    self.angle = angle;
                 }
-                self.is_waiting = false;
+                self.waypoints.remove(0);
+                step -= dist;
             } else {
-                self.speed = 0.0;
-                return;
+                let dir_x = dx / dist;
+                let dir_y = dy / dist;
+                self.position.0 += dir_x * step;
+                self.position.1 += dir_y * step;
+                step = 0.0;
             }
         }
-        // update pos of car
-        if !self.is_waiting {
-            if let Some(target) = self.waypoints.first() {
-                let dx = target.x - self.position.0;
-                let dy = target.y - self.position.1;
-                let dist = (dx * dx + dy * dy).sqrt();
-
-                if dist < self.speed {
-                    self.position = (target.x, target.y);
-                    if let Some(angle) = target.angle {
-                        self.angle = angle;
-                    }
-                    self.waypoints.remove(0);
-                } else {
-                    let dir_x = dx / dist;
-                    let dir_y = dy / dist;
-                    self.position.0 += dir_x * self.speed;
-                    self.position.1 += dir_y * self.speed;
-                }
-            }
+    }
+
+    // speed the car drives at when nothing holds it back
+    pub fn cruise_speed(&self) -> f64 {
+        let road = if self.in_intersection() && self.ring.is_some() {
+            RING_SPEED
+        } else if self.in_intersection() {
+            INTERSECTION_SPEED
+        } else {
+            ROAD_SPEED
+        };
+        road.min(self.vehicle.spec().max_speed)
+    }
+
+    // check if the car close to intsersection مفرق
+    pub fn in_intersection(&self) -> bool {
+        self.in_bounds(&self.intersection)
+    }
+
+    // How far the car can still drive before it reaches its stop line, None once inside
+    // the box. A longer vehicle stops further back so its nose stays where a car's would.
+    pub fn distance_to_stop_line(&self) -> Option<f64> {
+        if self.in_intersection() {
+            None
+        } else {
+            Some(self.distance_to_box() - STOP_LINE_OFFSET - self.overhang())
         }
     }
 
-    fn in_bounds(&self, bounds: &(RangeInclusive<f64>, RangeInclusive<f64>)) -> bool {
+    pub fn distance_to_box(&self) -> f64 {
+        let (x, y) = self.position;
+        let area = &self.intersection;
+        let dx = (area.0.start() - x).max(x - area.0.end()).max(0.0);
+        let dy = (area.1.start() - y).max(y - area.1.end()).max(0.0);
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    pub fn in_bounds(&self, bounds: &Area) -> bool {
         bounds.0.contains(&self.position.0) && bounds.1.contains(&self.position.1)
     }
 
-    fn is_car_in_front(&self, others: &[Car], safe_distance: f64) -> bool {
+    // nearest car ahead in the same lane, ahead on the ring, ahead on the exit lane the
+    // car merges into, or ahead on the road to the next intersection
+    pub fn car_in_front<'a>(&self, others: &'a [Car]) -> Option<&'a Car> {
+        let remaining = self.remaining_distance();
+        let on_ring = self.ring_angle();
+        let mut nearest: Option<(f64, &Car)> = None;
         for other in others {
-            if other.id == self.id {
+            if other.id == self.id || self.passes(other) {
                 continue;
             }
 
-            if other.direction == self.direction && other.lane == self.lane {
-                let dx = other.position.0 - self.position.0;
-                let dy = other.position.1 - self.position.1;
-                let dist = (dx * dx + dy * dy).sqrt();
+            let ahead = if let (Some(a), Some(b), Some((_, radius))) =
+                (on_ring, other.ring_rear(), self.ring)
+            {
+                // past where the car turns off only one leaving the same way is in its path,
+                // a long vehicle as long as its tail is not
+                let ahead_by = (b - a).rem_euclid(TAU);
+                ahead_by * radius < RING_LOOKAHEAD
+                    && (ahead_by <= self.ring_left(a) || self.exits_with(other))
+            } else if other.node != self.node {
+                !self.in_intersection() && !other.in_intersection() && self.drives_behind(other)
+            } else if self.shares_lane(other) && !other.has_left_box() {
+                // on the same route the one with less of it left is ahead, also past a turn
+                if self.lane == other.lane {
+                    other.remaining_distance() < remaining
+                } else if self.beside(other)
+                    && (self.about_to_move_over() || other.about_to_move_over())
+                    && !(self.is_waiting && other.is_waiting)
+                {
+                    // side by side the one cutting into the lane of the other waits, the
+                    // later one if they would swap lanes, and one already moving over into
+                    // the lane ahead of the other has the lane. Standing in a queue the one
+                    // further on goes first, so no three of them wait for each other.
+                    (self.cuts_into(other) && (!other.cuts_into(self) || self.id > other.id))
+                        || (other.moves_into(self) && self.is_ahead(other.position))
+                } else {
+                    self.is_ahead(other.position)
+                }
+            } else {
+                // out of the box, also from the same lane, only one leaving the same way is
+                self.exits_with(other)
+                    && other.has_left_box()
+                    && other.remaining_distance() < remaining
+            };
+            if ahead {
+                // nearest by the gap, a long vehicle further off may be closer than a car
+                let dist = self.gap_to(other.position, other.length());
+                if nearest.is_none_or(|(n, _)| dist < n) {
+                    nearest = Some((dist, other));
+                }
+            }
+        }
+        nearest.map(|(_, car)| car)
+    }
 
-                match self.direction {
-                    Direction::North if dy > 0.0 && dist < safe_distance => {
-                        return true;
-                    }
-                    Direction::South if dy < 0.0 && dist < safe_distance => {
-                        return true;
-                    }
-                    Direction::East if dx < 0.0 && dist < safe_distance => {
-                        return true;
-                    }
-                    Direction::West if dx > 0.0 && dist < safe_distance => {
-                        return true;
-                    }
-                    _ => {}
+    // Inbound lanes the car takes up where it is now, as a range. A car bound for a turn
+    // pocket drives in the lane next to it until the pocket opens and takes up every lane
+    // in between while it moves over, and a little before so it sees what it cuts into.
+    pub fn road_lanes(&self) -> (usize, usize) {
+        let index = self.lane.index;
+        match self.pocket {
+            Some((from, opens_at)) if !self.in_intersection() && !self.has_left_box() => {
+                let dist = self.distance_to_box();
+                let change = LANE_CHANGE_LENGTH * from.abs_diff(index) as f64;
+                if dist > opens_at + change + MERGE_LOOKAHEAD {
+                    (from, from)
+                } else if dist > opens_at {
+                    (from.min(index), from.max(index))
+                } else {
+                    (index, index)
                 }
             }
+            _ => (index, index),
+        }
+    }
+
+    // between lanes, on its way from one to another
+    fn moving_over(&self) -> bool {
+        let (from, to) = self.road_lanes();
+        from != to
+    }
+
+    // the lane it drives in before moving over, or the one it stays in
+    fn lane_now(&self) -> usize {
+        match self.pocket {
+            Some((from, _)) if self.moving_over() => from,
+            _ => self.lane.index,
+        }
+    }
+
+    // still in its own lane but close to where it starts moving over
+    fn about_to_move_over(&self) -> bool {
+        let index = self.lane.index;
+        match self.pocket {
+            Some((from, opens_at))
+                if from != index && !self.in_intersection() && !self.has_left_box() =>
+            {
+                let dist = self.distance_to_box();
+                let change = LANE_CHANGE_LENGTH * from.abs_diff(index) as f64;
+                dist > opens_at + change && dist <= opens_at + change + MERGE_LOOKAHEAD
+            }
+            _ => false,
         }
-        false
     }
 
-    fn conflicts_with(&self, other: &Car) -> bool {
-        use Direction::*;
-        use Lane::*;
+    // about to move over into the lane the other car drives in
+    fn cuts_into(&self, other: &Car) -> bool {
+        let (low, high) = self.road_lanes();
+        let theirs = other.lane_now();
+        self.about_to_move_over() && (low..=high).contains(&theirs) && theirs != self.lane_now()
+    }
+
+    // already on its way over into the lane the other car drives in
+    fn moves_into(&self, other: &Car) -> bool {
+        let (low, high) = self.road_lanes();
+        let theirs = other.lane_now();
+        self.moving_over()
+            && !self.about_to_move_over()
+            && (low..=high).contains(&theirs)
+            && theirs != self.lane_now()
+    }
 
-        if self.id == other.id {
-            return false;
+    // the bodies overlap along the approach, whatever lanes they are in
+    fn beside(&self, other: &Car) -> bool {
+        let dx = other.position.0 - self.position.0;
+        let dy = other.position.1 - self.position.1;
+        (dx * self.approach.0 + dy * self.approach.1).abs() < (self.length() + other.length()) / 2.0
+    }
+
+    // both come from the same side and drive in the same lane right now
+    pub fn shares_lane(&self, other: &Car) -> bool {
+        let (a, b) = (self.road_lanes(), other.road_lanes());
+        self.direction == other.direction && a.0 <= b.1 && b.0 <= a.1
+    }
+
+    // both come in on the same lane at the same intersection, maybe on different sides
+    // of it, a car bound for a turn pocket on the lane next to the pocket
+    pub fn enters_with(&self, other: &Car) -> bool {
+        let entry = |car: &Car| car.pocket.map_or(car.lane.index, |(from, _)| from);
+        self.node == other.node && self.direction == other.direction && entry(self) == entry(other)
+    }
+
+    // both routes end on the same exit lane, maybe on different sides of it
+    pub fn exits_with(&self, other: &Car) -> bool {
+        match (self.waypoints.last(), other.waypoints.last()) {
+            (Some(a), Some(b)) => {
+                (a.x - b.x).hypot(a.y - b.y) < (self.side - other.side).abs() + 1.0
+            }
+            _ => false,
         }
+    }
 
-        match (self.direction, self.lane, other.direction, other.lane) {
-            // Same direction
-            (a, _, b, _) if a == b => false,
+    // Side by side in a lane the two leave room enough between them to pass, so neither
+    // follows the other. Not on a ring, and not while one has driven into the box and
+    // the other has still to, the one waiting at the line lets the other get ahead.
+    fn passes(&self, other: &Car) -> bool {
+        let between = (self.side - other.side).abs() - (self.width() + other.width()) / 2.0;
+        between >= PASSING_GAP
+            && self.in_intersection() == other.in_intersection()
+            && self.ring_angle().is_none()
+            && other.ring_angle().is_none()
+    }
+
+    // how far the body sticks out past that of a car, front and back
+    pub fn overhang(&self) -> f64 {
+        (self.length() - VEHICLE_LENGTH).max(0.0) / 2.0
+    }
 
-            // Allow North vs South if both go straight
-            (North, Straight, South, Straight) => false,
-            (South, Straight, North, Straight) => false,
+    // The body is in the box. The box leaves room for a car sticking out of it, so this
+    // is the centre being inside for a car, and a longer vehicle blocks it for longer.
+    pub fn occupies_box(&self) -> bool {
+        self.in_intersection() || self.distance_to_box() < self.overhang()
+    }
 
-            // Allow East vs West if both go straight
-            (East, Straight, West, Straight) => false,
-            (West, Straight, East, Straight) => false,
+    // clear of the box and driving away from it
+    pub fn has_left_box(&self) -> bool {
+        let area = &self.intersection;
+        let cx = (area.0.start() + area.0.end()) / 2.0;
+        let cy = (area.1.start() + area.1.end()) / 2.0;
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        !self.occupies_box() && sin * (self.position.0 - cx) - cos * (self.position.1 - cy) > 0.0
+    }
 
-            _ => true,
+    // length of the path still to drive
+    pub fn remaining_distance(&self) -> f64 {
+        let mut from = self.position;
+        let mut total = 0.0;
+        for w in &self.waypoints {
+            total += ((w.x - from.0).powi(2) + (w.y - from.1).powi(2)).sqrt();
+            from = (w.x, w.y);
         }
+        total
+    }
+
+    // Angle round the roundabout, growing the way traffic goes round, while the car is
+    // on the ring lane, or of its tail while that is still on it. None anywhere else.
+    pub fn ring_angle(&self) -> Option<f64> {
+        let (front, tail) = self.ring_ends()?;
+        front.or(tail)
+    }
+
+    // the same of its tail while that is on the ring, where a car behind catches up
+    // with it
+    fn ring_rear(&self) -> Option<f64> {
+        let (front, tail) = self.ring_ends()?;
+        tail.or(front)
+    }
+
+    // angles of the front and the tail of the car, each while it is on the ring lane
+    fn ring_ends(&self) -> Option<(Option<f64>, Option<f64>)> {
+        let ((cx, cy), radius) = self.ring?;
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        let half = self.length() / 2.0;
+        let tail = (self.position.0 - sin * half, self.position.1 + cos * half);
+        let on_ring = |(x, y): Point| {
+            let (dx, dy) = (x - cx, y - cy);
+            (((dx * dx + dy * dy).sqrt() - radius).abs() <= VEHICLE_WIDTH).then(|| (-dy).atan2(dx))
+        };
+        Some((on_ring(self.position), on_ring(tail)))
+    }
+
+    // how far round from `at` the car still drives before it turns off the ring
+    fn ring_left(&self, at: f64) -> f64 {
+        let Some(((cx, cy), radius)) = self.ring else {
+            return 0.0;
+        };
+        self.waypoints
+            .iter()
+            .rev()
+            .find(|w| ((w.x - cx).hypot(w.y - cy) - radius).abs() < VEHICLE_WIDTH)
+            .map_or(0.0, |w| ((cy - w.y).atan2(w.x - cx) - at).rem_euclid(TAU))
+    }
+
+    // Between two intersections a car may follow one already on its route through the
+    // next: ahead, facing about the same way and in the car's path, without the room
+    // beside it to pass that it needs in a lane.
+    fn drives_behind(&self, other: &Car) -> bool {
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        let dx = other.position.0 - self.position.0;
+        let dy = other.position.1 - self.position.1;
+        let along = dx * sin - dy * cos;
+        let across = dx * cos + dy * sin;
+        let turned = (other.angle - self.angle + 180.0).rem_euclid(360.0) - 180.0;
+        // a car turned towards another lane sticks out further to the side
+        let reach = (self.width() + other.width()) / 2.0
+            + other.length() / 2.0 * turned.to_radians().sin().abs();
+        along > 0.0 && across.abs() < reach + PASSING_GAP && turned.abs() < 45.0
+    }
+
+    // further along the approach than the car
+    pub fn is_ahead(&self, pos: (f64, f64)) -> bool {
+        let dx = pos.0 - self.position.0;
+        let dy = pos.1 - self.position.1;
+        dx * self.approach.0 + dy * self.approach.1 > 0.0
+    }
+
+    pub fn distance_to(&self, pos: (f64, f64)) -> f64 {
+        let dx = pos.0 - self.position.0;
+        let dy = pos.1 - self.position.1;
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    // bumper to bumper gap to a vehicle of that length in front whose centre is at pos
+    pub fn gap_to(&self, pos: (f64, f64), length: f64) -> f64 {
+        self.distance_to(pos) - (self.length() + length) / 2.0
     }
 
     pub fn has_finished(&self) -> bool {
         self.waypoints.is_empty()
     }
+}
+
+// The path moved `side` px to the right of the way it runs, for a vehicle riding off
+// the middle of its lane. Each point moves square to the path there.
+pub fn shift_path(start: Point, waypoints: &[Waypoint], side: f64) -> (Point, Vec<Waypoint>) {
+    let mut points = vec![start];
+    points.extend(waypoints.iter().map(|w| (w.x, w.y)));
+    let last = points.len() - 1;
+    let shifted: Vec<Point> = (0..points.len())
+        .map(|i| {
+            let (a, b) = (points[i.saturating_sub(1)], points[(i + 1).min(last)]);
+            let len = (b.0 - a.0).hypot(b.1 - a.1);
+            if len < 1e-9 {
+                return points[i];
+            }
+            let r = right_of(((b.0 - a.0) / len, (b.1 - a.1) / len));
+            (points[i].0 + r.0 * side, points[i].1 + r.1 * side)
+        })
+        .collect();
+    let waypoints = waypoints
+        .iter()
+        .zip(&shifted[1..])
+        .map(|(w, &(x, y))| Waypoint {
+            x,
+            y,
+            angle: w.angle,
+        })
+        .collect();
+    (shifted[0], waypoints)
+}
 
-    pub fn render(&self, canvas: &mut Canvas<Window>) {
-        let (w, h) = self.size.unwrap_or((80, 60)); // default for cars
-
-        let rect = Rect::new(
-            (self.position.0 as i32) - (w as i32) / 2,
-            (self.position.1 as i32) - (h as i32) / 2,
-            w,
-            h,
-        );
-
-        canvas
-            .copy_ex(
-                self.texture,
-                None,
-                Some(rect),
-                self.angle,
-                None,
-                false,
-                false,
-            )
-            .unwrap();
+// unit vector from start to the first waypoint
+fn approach_along(start: (f64, f64), waypoints: &[Waypoint]) -> (f64, f64) {
+    match waypoints.first() {
+        Some(w) => {
+            let (dx, dy) = (w.x - start.0, w.y - start.1);
+            let len = (dx * dx + dy * dy).sqrt().max(1e-9);
+            (dx / len, dy / len)
+        }
+        None => (0.0, 0.0),
     }
 }
+
+// sprite angle for a direction of travel: 0 is up the screen, 90 is to the right
+pub fn heading(dx: f64, dy: f64) -> f64 {
+    dx.atan2(-dy).to_degrees().rem_euclid(360.0)
+}
@@ -0,0 +1,239 @@
+use std::str::FromStr;
+
+use crate::car::Direction;
+use crate::demand::Demand;
+use crate::following::Idm;
+use crate::layout::Layout;
+use crate::network::Network;
+use crate::policy::POLICY_NAMES;
+use crate::scenario::{Scenario, Spawn};
+use crate::spawn_cars::{TurnRadii, Turning, TurningRatios};
+
+// Command line options understood by both the window and the headless runner.
+pub struct Options {
+    // None lets the caller pick a seed
+    pub seed: Option<u64>,
+    pub policy: String,
+    // how long the headless runner simulates
+    pub seconds: f64,
+    // file the headless runner writes the trip records to
+    pub trips: Option<String>,
+    pub following: Idm,
+    pub turn_radii: TurnRadii,
+    pub turning: TurningRatios,
+    pub pause_on_collision: bool,
+    // intersections clear a path for emergency vehicles
+    pub preemption: bool,
+    // planes generated per minute
+    pub planes: f64,
+    // pedestrians coming to the crosswalks per minute
+    pub pedestrians: f64,
+    // percent of the random traffic that are cyclists
+    pub cyclists: f64,
+    // percent of the random traffic driven by humans
+    pub humans: f64,
+    pub network: Network,
+    // cars a scenario spawns at set times
+    pub schedule: Vec<Spawn>,
+    // arrivals generated on each approach
+    pub demand: Vec<Demand>,
+    // whether random cars are spawned as well, None leaves it to the runner: the
+    // headless runner always spawns them, the window waits for R
+    pub random_traffic: Option<bool>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            seed: None,
+            policy: "priority".to_string(),
+            seconds: 60.0,
+            trips: None,
+            following: Idm::default(),
+            turn_radii: TurnRadii::default(),
+            turning: TurningRatios::default(),
+            pause_on_collision: false,
+            preemption: true,
+            planes: 0.0,
+            pedestrians: 0.0,
+            cyclists: 0.0,
+            humans: 0.0,
+            network: Network::single(Layout::default()),
+            schedule: Vec::new(),
+            demand: Vec::new(),
+            random_traffic: None,
+        }
+    }
+}
+
+impl Options {
+    // parses std::env::args, prints the problem and exits on bad input
+    pub fn from_args() -> Self {
+        let mut options = Options::default();
+        let mut policy_given = false;
+        let mut scenario: Option<Scenario> = None;
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => options.seed = Some(value(&arg, args.next())),
+                "--policy" => {
+                    options.set_policy(args.next().unwrap_or_default());
+                    policy_given = true;
+                }
+                "--seconds" => options.seconds = value(&arg, args.next()),
+                "--trips" => match args.next() {
+                    Some(path) => options.trips = Some(path),
+                    None => fail("--trips expects a file"),
+                },
+                "--headway" => options.following.time_headway = value(&arg, args.next()),
+                "--min-gap" => options.following.min_gap = value(&arg, args.next()),
+                "--right-radius" => options.turn_radii.right = value(&arg, args.next()),
+                "--left-radius" => options.turn_radii.left = value(&arg, args.next()),
+                "--turning" => {
+                    let (side, turning) = turning(args.next().unwrap_or_default());
+                    options.turning.set(side, turning);
+                }
+                "--pause-on-collision" => options.pause_on_collision = true,
+                "--no-preemption" => options.preemption = false,
+                "--planes" => options.planes = value(&arg, args.next()),
+                "--pedestrians" => options.pedestrians = value(&arg, args.next()),
+                "--cyclists" => options.cyclists = value(&arg, args.next()),
+                "--humans" => options.humans = value(&arg, args.next()),
+                "--layout" => {
+                    let name = args.next().unwrap_or_default();
+                    let layout = Layout::from_arg(&name).unwrap_or_else(|e| fail(&e));
+                    options.network = Network::single(layout);
+                }
+                "--network" => {
+                    let path = args.next().unwrap_or_default();
+                    options.network = Network::load(&path).unwrap_or_else(|e| fail(&e));
+                }
+                "--scenario" => {
+                    let path = args.next().unwrap_or_default();
+                    let s = Scenario::load(&path).unwrap_or_else(|e| fail(&e));
+                    policy_given |= s.policy.is_some();
+                    options.apply(&s);
+                    scenario = Some(s);
+                }
+                other => fail(&format!("unknown argument: {}", other)),
+            }
+        }
+        // spawns name arms, so they are checked against the road the run ends up on
+        if let Some(s) = &scenario {
+            options.schedule = s.schedule(&options.network).unwrap_or_else(|e| fail(&e));
+            options.demand = s.demand(&options.network).unwrap_or_else(|e| fail(&e));
+        }
+        if let Err(e) = options.turning.check(&options.network) {
+            fail(&e);
+        }
+        if !(0.0..=100.0).contains(&options.cyclists) {
+            fail("the share of cyclists is a percentage, 0 to 100");
+        }
+        if !(0.0..=100.0).contains(&options.humans) {
+            fail("the share of human drivers is a percentage, 0 to 100");
+        }
+        let idm = &options.following;
+        if !(idm.time_headway.is_finite() && idm.time_headway >= 0.0) {
+            fail("the headway is a time in seconds, finite and not negative");
+        }
+        // with no gap at all cars stop bumper to bumper and run into each other
+        if !(idm.min_gap.is_finite() && idm.min_gap > 0.0) {
+            fail("the minimum gap is a distance in px, finite and positive");
+        }
+        let radii = &options.turn_radii;
+        if ![radii.right, radii.left]
+            .iter()
+            .all(|r| r.is_finite() && *r > 0.0)
+        {
+            fail("the turn radii are distances in px, finite and positive");
+        }
+        // cars give way at the entry of a roundabout unless told otherwise
+        for node in &mut options.network.nodes {
+            if !policy_given && node.policy.is_none() && node.layout.roundabout.is_some() {
+                node.policy = Some("yield".to_string());
+            }
+        }
+        options
+    }
+
+    fn set_policy(&mut self, name: String) {
+        if !POLICY_NAMES.contains(&name.as_str()) {
+            fail(&format!(
+                "unknown policy {:?}, expected one of {:?}",
+                name, POLICY_NAMES
+            ));
+        }
+        self.policy = name;
+    }
+
+    // the settings of a scenario, flags after --scenario still override them
+    fn apply(&mut self, scenario: &Scenario) {
+        if let Some(road) = scenario.road().unwrap_or_else(|e| fail(&e)) {
+            self.network = road;
+        }
+        if let Some(name) = &scenario.policy {
+            self.set_policy(name.clone());
+        }
+        self.seed = scenario.seed.or(self.seed);
+        self.seconds = scenario.seconds.unwrap_or(self.seconds);
+        let idm = &mut self.following;
+        idm.time_headway = scenario.headway.unwrap_or(idm.time_headway);
+        idm.min_gap = scenario.min_gap.unwrap_or(idm.min_gap);
+        let radii = &mut self.turn_radii;
+        radii.right = scenario.right_radius.unwrap_or(radii.right);
+        radii.left = scenario.left_radius.unwrap_or(radii.left);
+        self.turning = scenario.turning.unwrap_or(self.turning);
+        self.preemption = scenario.preemption.unwrap_or(self.preemption);
+        self.planes = scenario.planes.unwrap_or(self.planes);
+        self.pedestrians = scenario.pedestrians.unwrap_or(self.pedestrians);
+        self.cyclists = scenario.cyclists.unwrap_or(self.cyclists);
+        self.humans = scenario.humans.unwrap_or(self.humans);
+        self.random_traffic = Some(scenario.random_traffic);
+    }
+}
+
+fn value<T: FromStr>(flag: &str, arg: Option<String>) -> T {
+    match arg.and_then(|v| v.parse().ok()) {
+        Some(v) => v,
+        None => fail(&format!("{} expects a number", flag)),
+    }
+}
+
+// [SIDE=]LEFT,STRAIGHT,RIGHT, shares for one side or all of them
+fn turning(arg: String) -> (Option<Direction>, Turning) {
+    let usage =
+        || -> ! { fail("--turning expects [SIDE=]LEFT,STRAIGHT,RIGHT, e.g. north=15,60,25") };
+    let (side, shares) = match arg.split_once('=') {
+        Some((side, shares)) => {
+            let side = match side {
+                "north" => Direction::North,
+                "south" => Direction::South,
+                "east" => Direction::East,
+                "west" => Direction::West,
+                _ => usage(),
+            };
+            (Some(side), shares)
+        }
+        None => (None, arg.as_str()),
+    };
+    let shares: Vec<f64> = shares
+        .split(',')
+        .map(|v| v.trim().parse().unwrap_or_else(|_| usage()))
+        .collect();
+    let [left, straight, right] = shares[..] else {
+        usage();
+    };
+    (
+        side,
+        Turning {
+            left,
+            straight,
+            right,
+        },
+    )
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(2);
+}
@@ -0,0 +1,224 @@
+use std::collections::HashSet;
+
+use crate::car::Car;
+use crate::pedestrian::Pedestrian;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Collision {
+    pub time: f64,
+    pub ids: (usize, usize),
+    // midpoint between the two cars
+    pub position: (f64, f64),
+}
+
+// Oriented bounding box of a vehicle body
+#[derive(Debug, Clone, Copy)]
+pub struct Obb {
+    pub center: (f64, f64),
+    // unit vector along the heading and the one across it
+    pub axes: [(f64, f64); 2],
+    pub half_extents: (f64, f64),
+}
+
+impl Obb {
+    pub fn of(car: &Car) -> Self {
+        let rad = car.angle.to_radians();
+        let forward = (rad.sin(), -rad.cos());
+        let side = (-forward.1, forward.0);
+        Obb {
+            center: car.position,
+            axes: [forward, side],
+            half_extents: (car.length() / 2.0, car.width() / 2.0),
+        }
+    }
+
+    // half the length of the box projected on `axis`
+    fn radius_on(&self, axis: (f64, f64)) -> f64 {
+        let [forward, side] = self.axes;
+        self.half_extents.0 * dot(forward, axis).abs() + self.half_extents.1 * dot(side, axis).abs()
+    }
+
+    // separating axis test, the four box axes are the only candidates in 2D
+    pub fn overlaps(&self, other: &Obb) -> bool {
+        let between = (
+            other.center.0 - self.center.0,
+            other.center.1 - self.center.1,
+        );
+        self.axes
+            .iter()
+            .chain(other.axes.iter())
+            .all(|&axis| dot(between, axis).abs() < self.radius_on(axis) + other.radius_on(axis))
+    }
+}
+
+fn dot(a: (f64, f64), b: (f64, f64)) -> f64 {
+    a.0 * b.0 + a.1 * b.1
+}
+
+// Checks every pair of road vehicles each tick. A pair is reported once when it
+// starts to overlap, not again on every tick it stays that way.
+#[derive(Default)]
+pub struct CollisionDetector {
+    touching: HashSet<(usize, usize)>,
+    // (car, pedestrian) pairs, the ids of pedestrians are their own
+    struck: HashSet<(usize, usize)>,
+}
+
+impl CollisionDetector {
+    pub fn detect(&mut self, cars: &[Car], time: f64) -> Vec<Collision> {
+        let road: Vec<(&Car, Obb)> = cars.iter().map(|c| (c, Obb::of(c))).collect();
+
+        let mut touching = HashSet::new();
+        let mut new = Vec::new();
+        for (i, (a, a_box)) in road.iter().enumerate() {
+            for (b, b_box) in &road[i + 1..] {
+                if !a_box.overlaps(b_box) {
+                    continue;
+                }
+                let ids = (a.id.min(b.id), a.id.max(b.id));
+                touching.insert(ids);
+                if !self.touching.contains(&ids) {
+                    new.push(Collision {
+                        time,
+                        ids,
+                        position: (
+                            (a.position.0 + b.position.0) / 2.0,
+                            (a.position.1 + b.position.1) / 2.0,
+                        ),
+                    });
+                }
+            }
+        }
+        self.touching = touching;
+        new
+    }
+
+    // vehicles running into pedestrians, reported with ids (car, pedestrian)
+    pub fn detect_pedestrians(
+        &mut self,
+        cars: &[Car],
+        walkers: &[Pedestrian],
+        time: f64,
+    ) -> Vec<Collision> {
+        let mut struck = HashSet::new();
+        let mut new = Vec::new();
+        for walker in walkers {
+            let body = walker.body();
+            for car in cars {
+                if !Obb::of(car).overlaps(&body) {
+                    continue;
+                }
+                let ids = (car.id, walker.id);
+                struck.insert(ids);
+                if !self.struck.contains(&ids) {
+                    new.push(Collision {
+                        time,
+                        ids,
+                        position: walker.position,
+                    });
+                }
+            }
+        }
+        self.struck = struck;
+        new
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::car::{Direction, Lane, Movement, VEHICLE_LENGTH, VEHICLE_WIDTH};
+
+    // a box of the given half extents turned by `angle` degrees, the way Obb::of turns one
+    fn turned(center: (f64, f64), angle: f64, half_extents: (f64, f64)) -> Obb {
+        let rad = angle.to_radians();
+        let forward = (rad.sin(), -rad.cos());
+        Obb {
+            center,
+            axes: [forward, (-forward.1, forward.0)],
+            half_extents,
+        }
+    }
+
+    fn car(id: usize, position: (f64, f64), angle: f64) -> Car {
+        let lane = Lane {
+            index: 0,
+            movement: Movement::Straight,
+        };
+        let mut car = Car::new(
+            lane,
+            position,
+            Vec::new(),
+            0.0,
+            id,
+            Direction::North,
+            (0.0..=0.0, 0.0..=0.0),
+            0,
+        );
+        car.angle = angle;
+        car
+    }
+
+    #[test]
+    fn rotated_boxes_meet_at_their_corners() {
+        // squares turned 45 degrees reach 10 * sqrt(2) from their centres along x
+        let reach = 10.0 * 2f64.sqrt();
+        let a = turned((0.0, 0.0), 45.0, (10.0, 10.0));
+        let b = turned((2.0 * reach - 0.1, 0.0), 45.0, (10.0, 10.0));
+        assert!(a.overlaps(&b) && b.overlaps(&a));
+        let b = turned((2.0 * reach + 0.1, 0.0), 45.0, (10.0, 10.0));
+        assert!(!a.overlaps(&b) && !b.overlaps(&a));
+    }
+
+    #[test]
+    fn a_corner_pokes_into_a_side() {
+        let a = turned((0.0, 0.0), 0.0, (10.0, 20.0));
+        let reach = 10.0 * 2f64.sqrt();
+        assert!(a.overlaps(&turned((20.0 + reach - 0.5, 0.0), 45.0, (10.0, 10.0))));
+        assert!(!a.overlaps(&turned((20.0 + reach + 0.5, 0.0), 45.0, (10.0, 10.0))));
+    }
+
+    #[test]
+    fn boxes_just_apart_do_not_overlap() {
+        let a = turned((0.0, 0.0), 0.0, (25.0, 15.0));
+        // side by side with a hair between them, and nose to tail
+        assert!(!a.overlaps(&turned((30.01, 0.0), 0.0, (25.0, 15.0))));
+        assert!(!a.overlaps(&turned((0.0, 50.01), 0.0, (25.0, 15.0))));
+        // close on both axes but apart along the diagonal
+        assert!(!a.overlaps(&turned((29.0, 49.0), 30.0, (25.0, 15.0))));
+        assert!(a.overlaps(&turned((29.9, 0.0), 0.0, (25.0, 15.0))));
+    }
+
+    #[test]
+    fn a_collision_is_reported_once_while_it_lasts() {
+        let mut detector = CollisionDetector::default();
+        let apart = [car(1, (0.0, 0.0), 0.0), car(2, (0.0, 100.0), 0.0)];
+        let touching = [
+            car(1, (0.0, 0.0), 0.0),
+            car(2, (0.0, VEHICLE_LENGTH - 1.0), 0.0),
+        ];
+        assert!(detector.detect(&apart, 0.0).is_empty());
+
+        let hit = detector.detect(&touching, 1.0);
+        assert_eq!(hit.len(), 1);
+        assert_eq!(hit[0].ids, (1, 2));
+        assert_eq!(hit[0].time, 1.0);
+        assert!(detector.detect(&touching, 2.0).is_empty());
+
+        // parting and running into each other again is a new collision
+        assert!(detector.detect(&apart, 3.0).is_empty());
+        assert_eq!(detector.detect(&touching, 4.0).len(), 1);
+    }
+
+    #[test]
+    fn a_car_turned_across_another_is_caught() {
+        let mut detector = CollisionDetector::default();
+        // the second car crosses in front, turned 90 degrees, its side just at the bumper
+        let front = VEHICLE_LENGTH / 2.0 + VEHICLE_WIDTH / 2.0;
+        let cars = [car(3, (0.0, 0.0), 0.0), car(7, (0.0, -front + 1.0), 90.0)];
+        assert_eq!(detector.detect(&cars, 0.0).len(), 1);
+        let mut detector = CollisionDetector::default();
+        let cars = [car(3, (0.0, 0.0), 0.0), car(7, (0.0, -front - 1.0), 90.0)];
+        assert!(detector.detect(&cars, 0.0).is_empty());
+    }
+}
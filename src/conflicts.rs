@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+
+use crate::car::{Area, Car, Direction, Lane, Movement, VEHICLE_WIDTH};
+use crate::layout::Layout;
+use crate::spawn_cars::TurnRadii;
+
+// width of the band a vehicle sweeps along its route, body plus some margin
+pub const SWEPT_WIDTH: f64 = 50.0;
+
+type Point = (f64, f64);
+type RouteKey = (Direction, Lane);
+
+// Which routes cross each other inside the box, worked out from the waypoint
+// polylines of `Layout::route`: two routes conflict when their pieces inside the
+// box come closer than one vehicle width. The closest approach is computed once for
+// every pair of routes, so wider vehicles can be checked against the same table.
+pub struct ConflictTable {
+    table: HashMap<(RouteKey, RouteKey), f64>,
+    vehicle_width: f64,
+}
+
+impl Default for ConflictTable {
+    fn default() -> Self {
+        Self::new(&Layout::default(), SWEPT_WIDTH, &TurnRadii::default())
+    }
+}
+
+impl ConflictTable {
+    pub fn new(layout: &Layout, vehicle_width: f64, radii: &TurnRadii) -> Self {
+        // a route along the edge of the box, as a turn off a slanted arm may be, still
+        // sweeps into it
+        let area = layout.intersection();
+        let reach = vehicle_width / 2.0;
+        let area = (
+            area.0.start() - reach..=area.0.end() + reach,
+            area.1.start() - reach..=area.1.end() + reach,
+        );
+        let mut routes = Vec::new();
+        for arm in &layout.arms {
+            for (index, spec) in arm.lanes.iter().enumerate() {
+                for &movement in &spec.movements {
+                    let lane = Lane { index, movement };
+                    if let Some((start, waypoints)) = layout.route(arm.from, lane, radii) {
+                        let mut points = vec![start];
+                        points.extend(waypoints.iter().map(|w| (w.x, w.y)));
+                        routes.push(((arm.from, lane), clip_to_box(&points, &area)));
+                    }
+                }
+            }
+        }
+
+        let mut table = HashMap::new();
+        for (a, a_segments) in &routes {
+            for (b, b_segments) in &routes {
+                // cars on the same route keep their distance by following, not by the policy
+                let closest = if a == b {
+                    f64::INFINITY
+                } else {
+                    a_segments
+                        .iter()
+                        .flat_map(|sa| b_segments.iter().map(|sb| segment_distance(*sa, *sb)))
+                        .fold(f64::INFINITY, f64::min)
+                };
+                table.insert((*a, *b), closest);
+            }
+        }
+        ConflictTable {
+            table,
+            vehicle_width,
+        }
+    }
+
+    pub fn routes_conflict(&self, a: RouteKey, b: RouteKey) -> bool {
+        self.closest(a, b) < self.vehicle_width
+    }
+
+    // true if the routes of the two cars may cross inside the box
+    pub fn between(&self, a: &Car, b: &Car) -> bool {
+        a.id != b.id
+            && self.closest((a.direction, a.lane), (b.direction, b.lane))
+                < self.vehicle_width + extra_sweep(a) + extra_sweep(b)
+    }
+
+    // unknown routes are taken to conflict with everything
+    fn closest(&self, a: RouteKey, b: RouteKey) -> f64 {
+        self.table.get(&(a, b)).copied().unwrap_or(0.0)
+    }
+}
+
+// How much further than a car a vehicle reaches to the side of its route. A wider
+// one does on both sides, one riding off the middle of its lane on that side, and a
+// longer one cuts the corner and swings out on a turn, and round a ring on every route.
+fn extra_sweep(car: &Car) -> f64 {
+    let wider = ((car.width() - VEHICLE_WIDTH) / 2.0 + car.side.abs()).max(0.0);
+    match car.lane.movement {
+        Movement::Straight if car.ring.is_none() => wider,
+        _ => wider + car.overhang(),
+    }
+}
+
+// parts of the polyline inside the box, as segments
+fn clip_to_box(points: &[Point], area: &Area) -> Vec<(Point, Point)> {
+    points
+        .windows(2)
+        .filter_map(|w| clip_segment(w[0], w[1], area))
+        .collect()
+}
+
+// Liang-Barsky clipping against the intersection box
+fn clip_segment(p: Point, q: Point, area: &Area) -> Option<(Point, Point)> {
+    let (dx, dy) = (q.0 - p.0, q.1 - p.1);
+    let checks = [
+        (-dx, p.0 - area.0.start()),
+        (dx, area.0.end() - p.0),
+        (-dy, p.1 - area.1.start()),
+        (dy, area.1.end() - p.1),
+    ];
+
+    let (mut t0, mut t1) = (0.0_f64, 1.0_f64);
+    for (edge, dist) in checks {
+        if edge == 0.0 {
+            if dist < 0.0 {
+                return None;
+            }
+        } else {
+            let t = dist / edge;
+            if edge < 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+    Some((
+        (p.0 + t0 * dx, p.1 + t0 * dy),
+        (p.0 + t1 * dx, p.1 + t1 * dy),
+    ))
+}
+
+fn point_segment_distance(p: Point, (a, b): (Point, Point)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len2 = dx * dx + dy * dy;
+    let t = if len2 == 0.0 {
+        0.0
+    } else {
+        (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len2).clamp(0.0, 1.0)
+    };
+    let (cx, cy) = (a.0 + t * dx - p.0, a.1 + t * dy - p.1);
+    (cx * cx + cy * cy).sqrt()
+}
+
+fn cross(o: Point, a: Point, b: Point) -> f64 {
+    (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+}
+
+// how close the two segments come, 0 where they cross
+pub fn segment_distance(s: (Point, Point), t: (Point, Point)) -> f64 {
+    let d1 = cross(t.0, t.1, s.0);
+    let d2 = cross(t.0, t.1, s.1);
+    let d3 = cross(s.0, s.1, t.0);
+    let d4 = cross(s.0, s.1, t.1);
+    if d1 * d2 < 0.0 && d3 * d4 < 0.0 {
+        return 0.0;
+    }
+    point_segment_distance(s.0, t)
+        .min(point_segment_distance(s.1, t))
+        .min(point_segment_distance(t.0, s))
+        .min(point_segment_distance(t.1, s))
+}
@@ -0,0 +1,106 @@
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::car::Movement;
+use crate::network::Approach;
+use crate::scenario::Spawn;
+use crate::simulation::DT;
+use crate::spawn_cars::{Request, Turning};
+
+// cars arriving together, one after the other on the same approach
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Platoon {
+    pub size: usize,
+    // seconds between the cars of a platoon
+    pub headway: f64,
+}
+
+// Traffic arriving on one approach: a rate in vehicles per hour that may change over
+// the run, with cars coming one at a time at random or in platoons.
+#[derive(Debug, Clone)]
+pub struct Demand {
+    pub origin: Approach,
+    // (seconds, vehicles per hour), linear in between and flat past the ends
+    pub profile: Vec<(f64, f64)>,
+    pub platoon: Option<Platoon>,
+    // the turning ratios of the simulation if None
+    pub turning: Option<Turning>,
+    // movements the approach has lanes for
+    pub movements: Vec<Movement>,
+}
+
+impl Demand {
+    pub fn rate_at(&self, time: f64) -> f64 {
+        let after = self.profile.partition_point(|&(t, _)| t <= time);
+        let before = after.checked_sub(1).and_then(|i| self.profile.get(i));
+        match (before, self.profile.get(after)) {
+            (Some(&(t0, r0)), Some(&(t1, r1))) => r0 + (r1 - r0) * (time - t0) / (t1 - t0),
+            (Some(&(_, r)), None) | (None, Some(&(_, r))) => r,
+            (None, None) => 0.0,
+        }
+    }
+
+    // Cars arriving in the tick that ends at `now`. Arrivals are a Poisson process,
+    // drawn as one trial per tick, of single cars or of whole platoons.
+    pub fn arrivals(&self, now: f64, rng: &mut impl Rng) -> Vec<Spawn> {
+        let (size, headway) = self.platoon.map_or((1, 0.0), |p| (p.size, p.headway));
+        let chance = self.rate_at(now) / 3600.0 / size as f64 * DT;
+        if !rng.gen_bool(chance.clamp(0.0, 1.0)) {
+            return Vec::new();
+        }
+        (0..size)
+            .map(|k| Spawn {
+                time: now + k as f64 * headway,
+                origin: self.origin,
+                request: Request {
+                    movement: self
+                        .turning
+                        .and_then(|turning| turning.pick(&self.movements, rng)),
+                    ..Request::default()
+                },
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::car::Direction;
+
+    fn demand(profile: Vec<(f64, f64)>) -> Demand {
+        Demand {
+            origin: (0, Direction::North),
+            profile,
+            platoon: None,
+            turning: None,
+            movements: vec![Movement::Straight],
+        }
+    }
+
+    #[test]
+    fn a_flat_rate_holds_all_run_long() {
+        let d = demand(vec![(0.0, 240.0)]);
+        for time in [0.0, 1.5, 3600.0] {
+            assert_eq!(d.rate_at(time), 240.0);
+        }
+    }
+
+    #[test]
+    fn a_profile_is_linear_between_its_points() {
+        let d = demand(vec![(10.0, 100.0), (20.0, 300.0), (40.0, 0.0)]);
+        assert_eq!(d.rate_at(10.0), 100.0);
+        assert_eq!(d.rate_at(15.0), 200.0);
+        assert_eq!(d.rate_at(20.0), 300.0);
+        assert_eq!(d.rate_at(30.0), 150.0);
+    }
+
+    #[test]
+    fn a_profile_is_flat_past_its_ends() {
+        let d = demand(vec![(10.0, 100.0), (20.0, 300.0)]);
+        assert_eq!(d.rate_at(0.0), 100.0);
+        assert_eq!(d.rate_at(25.0), 300.0);
+        assert_eq!(demand(Vec::new()).rate_at(5.0), 0.0);
+    }
+}
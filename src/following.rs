@@ -0,0 +1,52 @@
+// Intelligent Driver Model: a continuous acceleration from the car's own speed,
+// the bumper to bumper gap to whatever is ahead and how fast that closes.
+#[derive(Debug, Clone, Copy)]
+pub struct Idm {
+    // desired time gap to the car ahead, in seconds
+    pub time_headway: f64,
+    // bumper to bumper gap kept when standing, in px
+    pub min_gap: f64,
+    // how sharply the car stops accelerating near its target speed
+    pub delta: f64,
+}
+
+impl Default for Idm {
+    fn default() -> Self {
+        Idm {
+            time_headway: 1.0,
+            min_gap: 10.0,
+            delta: 4.0,
+        }
+    }
+}
+
+impl Idm {
+    // `leader` is (gap, speed) of what is ahead, a stop line counts as a leader standing still
+    pub fn acceleration(
+        &self,
+        speed: f64,
+        target: f64,
+        max_accel: f64,
+        comfortable_decel: f64,
+        leader: Option<(f64, f64)>,
+    ) -> f64 {
+        let free_road = if target > 0.0 {
+            max_accel * (1.0 - (speed / target).powf(self.delta))
+        } else {
+            -comfortable_decel
+        };
+        // slowing down to a lower target is not an emergency
+        let mut accel = free_road.max(-comfortable_decel);
+
+        if let Some((gap, leader_speed)) = leader {
+            let closing = speed - leader_speed;
+            let desired = self.min_gap
+                + (speed * self.time_headway
+                    + speed * closing / (2.0 * (max_accel * comfortable_decel).sqrt()))
+                .max(0.0);
+            let gap = gap.max(0.01);
+            accel -= max_accel * (desired / gap).powi(2);
+        }
+        accel
+    }
+}
@@ -0,0 +1,583 @@
+use std::collections::HashSet;
+use std::f64::consts::{FRAC_PI_6, TAU};
+
+use serde::Deserialize;
+
+use crate::car::{Area, Direction, Lane, Movement, STOP_LINE_OFFSET, Waypoint};
+use crate::spawn_cars::{TurnRadii, round_corners};
+
+// layouts that ship with the binary, the first one is used when no --layout is given
+const BUILT_IN: [(&str, &str); 4] = [
+    ("cross", include_str!("../layouts/cross.toml")),
+    ("t", include_str!("../layouts/t_junction.toml")),
+    ("y", include_str!("../layouts/y_junction.toml")),
+    ("roundabout", include_str!("../layouts/roundabout.toml")),
+];
+pub const LAYOUT_NAMES: [&str; 4] = ["cross", "t", "y", "roundabout"];
+// routes end this far past the end of their arm so cars drive out of sight
+const EXIT_OVERSHOOT: f64 = 40.0;
+// distance over which a car moves from its lane into a turn pocket
+pub const LANE_CHANGE_LENGTH: f64 = 120.0;
+// how far before it starts to move over a car looks out for traffic in the lanes it moves into
+pub const MERGE_LOOKAHEAD: f64 = 100.0;
+// a movement up to this many degrees off straight on still counts as straight
+const STRAIGHT_ANGLE: f64 = 30.0;
+const MIN_ARM_ANGLE: f64 = 30.0;
+const MIN_RING_RADIUS: f64 = 80.0;
+
+pub type Point = (f64, f64);
+
+// Geometry of the intersection, read from a TOML file (see layouts/cross.toml).
+// The routes cars drive, the box and the roads the window draws all come from here.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Layout {
+    // size of the world in px, the window gets the same size
+    pub width: f64,
+    pub height: f64,
+    // where the centre lines of the arms meet
+    pub center: Point,
+    pub lane_width: f64,
+    // clear space between the outermost lanes and the edge of the box
+    #[serde(default)]
+    pub box_margin: f64,
+    // a single lane ring instead of a box the arms cross in
+    pub roundabout: Option<Roundabout>,
+    pub arms: Vec<Arm>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Roundabout {
+    // of the middle of the ring lane
+    pub radius: f64,
+}
+
+// one road meeting the intersection, named by the side cars come from
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Arm {
+    pub from: Direction,
+    // inbound lanes from the centre line outwards
+    pub lanes: Vec<LaneSpec>,
+    // lanes leaving the intersection on this side
+    pub exit_lanes: usize,
+    pub lane_width: Option<f64>,
+    // centre to where cars enter and leave, the edge of the world if not given
+    pub length: Option<f64>,
+    // compass bearing of the arm seen from the centre, 0 is up and 90 is right,
+    // defaults to its side so only angled arms need one
+    pub bearing: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "LaneFile")]
+pub struct LaneSpec {
+    // movements allowed from this lane, more than one makes it a shared lane
+    pub movements: Vec<Movement>,
+    // a turn pocket, the lane only opens this far before the box
+    pub pocket: Option<f64>,
+}
+
+// a lane is written as "left", as ["straight", "right"] or as
+// { movements = ["left"], pocket = 150.0 }
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LaneFile {
+    One(Movement),
+    Shared(Vec<Movement>),
+    Full {
+        movements: Vec<Movement>,
+        pocket: Option<f64>,
+    },
+}
+
+impl From<LaneFile> for LaneSpec {
+    fn from(file: LaneFile) -> Self {
+        match file {
+            LaneFile::One(movement) => LaneSpec {
+                movements: vec![movement],
+                pocket: None,
+            },
+            LaneFile::Shared(movements) => LaneSpec {
+                movements,
+                pocket: None,
+            },
+            LaneFile::Full { movements, pocket } => LaneSpec { movements, pocket },
+        }
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout::parse(BUILT_IN[0].1).expect("built in layout is valid")
+    }
+}
+
+impl Layout {
+    // a built in layout by name, or else a file
+    pub fn from_arg(arg: &str) -> Result<Layout, String> {
+        match BUILT_IN.iter().find(|(name, _)| *name == arg) {
+            Some((_, text)) => Ok(Layout::parse(text).expect("built in layout is valid")),
+            None => Layout::load(arg),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Layout, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Layout::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Layout, String> {
+        let layout: Layout = toml::from_str(text).map_err(|e| e.to_string())?;
+        layout.validate()?;
+        Ok(layout)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        // NaN passes any comparison the wrong way, so every number is checked to be finite
+        let positive = |v: f64| v.is_finite() && v > 0.0;
+        if !positive(self.width) || !positive(self.height) || !positive(self.lane_width) {
+            return Err("width, height and lane_width must be positive".to_string());
+        }
+        if !self.center.0.is_finite() || !self.center.1.is_finite() {
+            return Err("center must be a finite point".to_string());
+        }
+        if !(self.box_margin.is_finite() && self.box_margin >= 0.0) {
+            return Err("box_margin must be finite and not negative".to_string());
+        }
+        let mut sides = HashSet::new();
+        for arm in &self.arms {
+            if !sides.insert(arm.from) {
+                return Err(format!("more than one arm from {:?}", arm.from));
+            }
+            if !arm.lane_width.is_none_or(positive) || !arm.length.is_none_or(positive) {
+                return Err(format!("arm from {:?}: sizes must be positive", arm.from));
+            }
+            if !arm.bearing.is_none_or(f64::is_finite) {
+                return Err(format!("arm from {:?}: bearing must be finite", arm.from));
+            }
+            for other in &self.arms {
+                let apart = turn_between(self.bearing_of(arm), self.bearing_of(other)).abs();
+                if other.from != arm.from && apart < MIN_ARM_ANGLE {
+                    return Err(format!(
+                        "arms from {:?} and {:?} are less than {} degrees apart",
+                        arm.from, other.from, MIN_ARM_ANGLE
+                    ));
+                }
+            }
+        }
+        for arm in &self.arms {
+            if self.length_of(arm) <= self.box_reach(arm.from) + STOP_LINE_OFFSET {
+                return Err(format!("arm from {:?} ends inside the box", arm.from));
+            }
+        }
+        if let Some(ring) = self.roundabout {
+            let widest = self
+                .arms
+                .iter()
+                .map(|arm| self.lane_width_of(arm))
+                .fold(0.0, f64::max);
+            if !ring.radius.is_finite() || ring.radius < MIN_RING_RADIUS.max(2.0 * widest) {
+                return Err(format!(
+                    "roundabout radius must be at least {:.0}",
+                    MIN_RING_RADIUS.max(2.0 * widest)
+                ));
+            }
+            if self
+                .arms
+                .iter()
+                .any(|arm| arm.lanes.len() > 1 || arm.exit_lanes > 1)
+            {
+                return Err("a roundabout has one lane in and one lane out per arm".to_string());
+            }
+        }
+        for arm in &self.arms {
+            for (index, lane) in arm.lanes.iter().enumerate() {
+                let name = format!("arm from {:?}, lane {}", arm.from, index);
+                let distinct: HashSet<&Movement> = lane.movements.iter().collect();
+                if lane.movements.is_empty() || distinct.len() != lane.movements.len() {
+                    return Err(format!("{}: needs distinct movements", name));
+                }
+                for &movement in &lane.movements {
+                    if self.exit_lane(arm, index, movement).is_none() {
+                        return Err(format!("{}: no exit lane for {:?}", name, movement));
+                    }
+                }
+                if let Some(pocket) = lane.pocket {
+                    if self.roundabout.is_some() {
+                        return Err(format!("{}: no turn pockets at a roundabout", name));
+                    }
+                    let room = self.length_of(arm) - self.box_reach(arm.from) - LANE_CHANGE_LENGTH;
+                    if !pocket.is_finite() || pocket <= 0.0 || pocket > room {
+                        return Err(format!(
+                            "{}: pocket must be between 0 and {:.0}",
+                            name, room
+                        ));
+                    }
+                    if self.entry_lane(arm, index).is_none() {
+                        return Err(format!("{}: pocket without a full lane to come from", name));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn arm(&self, from: Direction) -> Option<&Arm> {
+        self.arms.iter().find(|arm| arm.from == from)
+    }
+
+    // movements some inbound lane of the arm allows, in a fixed order
+    pub fn movements_from(&self, from: Direction) -> Vec<Movement> {
+        let lanes = self.arm(from).map_or(&[][..], |arm| &arm.lanes);
+        [Movement::Left, Movement::Straight, Movement::Right]
+            .into_iter()
+            .filter(|m| lanes.iter().any(|lane| lane.movements.contains(m)))
+            .collect()
+    }
+
+    pub fn lane_width_of(&self, arm: &Arm) -> f64 {
+        arm.lane_width.unwrap_or(self.lane_width)
+    }
+
+    pub fn bearing_of(&self, arm: &Arm) -> f64 {
+        arm.bearing.unwrap_or(match arm.from {
+            Direction::North => 0.0,
+            Direction::East => 90.0,
+            Direction::South => 180.0,
+            Direction::West => 270.0,
+        })
+    }
+
+    // unit vector cars on the arm drive along towards the centre, y points down the screen
+    pub fn inbound(&self, from: Direction) -> Point {
+        let bearing = self
+            .arm(from)
+            .map_or(0.0, |arm| self.bearing_of(arm))
+            .to_radians();
+        // exact on square arms, so lanes along the axes stay on whole pixels
+        let snap = |v: f64| if v.abs() < 1e-12 { 0.0 } else { v };
+        (snap(-bearing.sin()), snap(bearing.cos()))
+    }
+
+    pub fn length_of(&self, arm: &Arm) -> f64 {
+        arm.length.unwrap_or_else(|| {
+            // out from the centre until the edge of the world
+            let (dx, dy) = self.inbound(arm.from);
+            let reach = |d: f64, low: f64, high: f64| {
+                if d > 1e-9 {
+                    high / d
+                } else if d < -1e-9 {
+                    -low / d
+                } else {
+                    f64::INFINITY
+                }
+            };
+            reach(-dx, self.center.0, self.width - self.center.0).min(reach(
+                -dy,
+                self.center.1,
+                self.height - self.center.1,
+            ))
+        })
+    }
+
+    fn half_width(&self, arm: &Arm) -> f64 {
+        arm.lanes.len().max(arm.exit_lanes) as f64 * self.lane_width_of(arm)
+    }
+
+    // The box where the routes cross. At a square junction it is as wide as the widest
+    // road plus the margin, angled arms overlap further out and a roundabout takes the ring.
+    pub fn intersection(&self) -> Area {
+        let (mut half_x, mut half_y) = (0.0_f64, 0.0_f64);
+        if let Some(ring) = self.roundabout {
+            let half = ring.radius + self.lane_width / 2.0;
+            (half_x, half_y) = (half, half);
+        } else if self
+            .arms
+            .iter()
+            .all(|arm| self.bearing_of(arm) % 90.0 == 0.0)
+        {
+            for arm in &self.arms {
+                let half = self.half_width(arm);
+                match self.bearing_of(arm) as i64 % 180 {
+                    0 => half_x = half_x.max(half),
+                    _ => half_y = half_y.max(half),
+                }
+            }
+        } else {
+            // the edges of the two closest roads only part this far out
+            let widest = self
+                .arms
+                .iter()
+                .map(|arm| self.half_width(arm))
+                .fold(0.0, f64::max);
+            let narrowest = self
+                .arms
+                .iter()
+                .flat_map(|a| self.arms.iter().map(move |b| (a, b)))
+                .filter(|(a, b)| a.from != b.from)
+                .map(|(a, b)| turn_between(self.bearing_of(a), self.bearing_of(b)).abs())
+                .fold(180.0, f64::min);
+            let half = widest / (narrowest / 2.0).to_radians().tan().min(1.0);
+            (half_x, half_y) = (half, half);
+        }
+        let (cx, cy) = self.center;
+        let (half_x, half_y) = (half_x + self.box_margin, half_y + self.box_margin);
+        (cx - half_x..=cx + half_x, cy - half_y..=cy + half_y)
+    }
+
+    // distance from the centre to the edge of the box, along the arm
+    pub fn box_reach(&self, from: Direction) -> f64 {
+        let area = self.intersection();
+        let (dx, dy) = self.inbound(from);
+        let half_x = (area.0.end() - area.0.start()) / 2.0;
+        let half_y = (area.1.end() - area.1.start()) / 2.0;
+        (half_x / dx.abs().max(1e-9)).min(half_y / dy.abs().max(1e-9))
+    }
+
+    // a point `offset` px to the right of the arm's centre line, seen by inbound cars,
+    // and `along` px out from the centre
+    pub fn point_on(&self, from: Direction, offset: f64, along: f64) -> Point {
+        let d = self.inbound(from);
+        let r = right_of(d);
+        (
+            self.center.0 - d.0 * along + r.0 * offset,
+            self.center.1 - d.1 * along + r.1 * offset,
+        )
+    }
+
+    // point on the middle of inbound lane i of the arm
+    fn lane_point(&self, arm: &Arm, i: usize, along: f64) -> Point {
+        let offset = (i as f64 + 0.5) * self.lane_width_of(arm);
+        self.point_on(arm.from, offset, along)
+    }
+
+    // same for exit lane j, those lie left of the centre line
+    fn exit_point(&self, arm: &Arm, j: usize, along: f64) -> Point {
+        let offset = (j as f64 + 0.5) * self.lane_width_of(arm);
+        self.point_on(arm.from, -offset, along)
+    }
+
+    // the lane cars for lane i start in: the lane itself, or for a turn pocket the
+    // nearest full length lane, outwards first
+    pub fn entry_lane(&self, arm: &Arm, i: usize) -> Option<usize> {
+        let full = |j: usize| arm.lanes.get(j).is_some_and(|lane| lane.pocket.is_none());
+        (0..arm.lanes.len())
+            .flat_map(|step| [i + step, i.wrapping_sub(step)])
+            .find(|&j| full(j))
+    }
+
+    // what a car in this lane needs to know about its pocket, see `Car::pocket`
+    pub fn pocket(&self, from: Direction, lane: Lane) -> Option<(usize, f64)> {
+        let index = lane.index;
+        let arm = self.arm(from)?;
+        let pocket = arm.lanes.get(index)?.pocket?;
+        Some((self.entry_lane(arm, index)?, pocket))
+    }
+
+    // Exit lane a movement from inbound lane i ends in. The k-th left turning lane from
+    // the centre line turns into the k-th exit lane, right turns count from the outside,
+    // and straight lanes carry on side by side, lined up with the exit lanes where they can.
+    pub fn exit_lane(&self, arm: &Arm, i: usize, movement: Movement) -> Option<usize> {
+        let exit = self.exit_arm(arm, movement)?;
+        let last = exit.exit_lanes.checked_sub(1)?;
+        let allows = |j: &usize| arm.lanes[*j].movements.contains(&movement);
+        let j = match movement {
+            Movement::Left => (0..i).filter(allows).count().min(last),
+            Movement::Right => last - (i + 1..arm.lanes.len()).filter(allows).count().min(last),
+            Movement::Straight => {
+                let first = (0..arm.lanes.len()).find(allows)?;
+                let count = (0..arm.lanes.len()).filter(allows).count();
+                let k = (0..i).filter(allows).count();
+                (k + first.min(exit.exit_lanes.saturating_sub(count))).min(last)
+            }
+        };
+        Some(j)
+    }
+
+    // The arm a movement leaves on, from the angle between the arms: within 30 degrees of
+    // straight on is straight, anything sharper to either side a turn. When several arms
+    // fit, the one closest to a square turn or to straight on wins.
+    pub fn exit_arm(&self, arm: &Arm, movement: Movement) -> Option<&Arm> {
+        let heading = self.bearing_of(arm) + 180.0;
+        let ideal = match movement {
+            Movement::Left => -90.0,
+            Movement::Straight => 0.0,
+            Movement::Right => 90.0,
+        };
+        self.arms
+            .iter()
+            .filter(|exit| exit.from != arm.from)
+            .map(|exit| (exit, turn_between(heading, self.bearing_of(exit))))
+            .filter(|&(_, turn)| match movement {
+                Movement::Left => turn < -STRAIGHT_ANGLE,
+                Movement::Straight => turn.abs() <= STRAIGHT_ANGLE,
+                Movement::Right => turn > STRAIGHT_ANGLE,
+            })
+            .min_by(|a, b| (a.1 - ideal).abs().total_cmp(&(b.1 - ideal).abs()))
+            .map(|(exit, _)| exit)
+    }
+
+    // start point and waypoints of the road through the intersection for an approach and lane
+    pub fn route(
+        &self,
+        from: Direction,
+        lane: Lane,
+        radii: &TurnRadii,
+    ) -> Option<(Point, Vec<Waypoint>)> {
+        let Lane { index, movement } = lane;
+        let arm = self.arm(from)?;
+        let spec = arm.lanes.get(index)?;
+        if !spec.movements.contains(&movement) {
+            return None;
+        }
+        let exit = self.exit_arm(arm, movement)?;
+        let j = self.exit_lane(arm, index, movement)?;
+
+        let length = self.length_of(arm);
+        let entry = self.entry_lane(arm, index)?;
+        let start = self.lane_point(arm, entry, length);
+        let mut corners = Vec::new();
+        if let Some(pocket) = spec.pocket {
+            let opens = self.box_reach(from) + pocket;
+            corners.push(self.lane_point(arm, entry, opens + LANE_CHANGE_LENGTH));
+            corners.push(self.lane_point(arm, index, opens));
+        }
+
+        let lane_start = self.lane_point(arm, index, length);
+        let end = self.exit_point(exit, j, self.length_of(exit) + EXIT_OVERSHOOT);
+        let d = self.inbound(from);
+        let e = self.inbound(exit.from);
+        if let Some(ring) = self.roundabout {
+            corners.extend(self.around_ring(ring, (lane_start, d), (end, (-e.0, -e.1)))?);
+        } else if movement == Movement::Straight {
+            if cross(d, (end.0 - lane_start.0, end.1 - lane_start.1)).abs() >= 0.5 {
+                // the lanes do not line up, shift over inside the box
+                corners.push(self.lane_point(arm, index, self.box_reach(from)));
+                corners.push(self.exit_point(exit, j, self.box_reach(exit.from)));
+            }
+        } else {
+            // the exit lane runs from the centre outwards, against the arm's inbound vector
+            corners.push(line_crossing(lane_start, d, end, (-e.0, -e.1))?);
+        }
+        corners.push(end);
+
+        let corners: Vec<Waypoint> = corners
+            .into_iter()
+            .map(|(x, y)| Waypoint { x, y, angle: None })
+            .collect();
+        let radius = match self.roundabout {
+            Some(_) => radii.right,
+            None => radii.for_movement(movement),
+        };
+        Some((start, round_corners(start, &corners, radius)))
+    }
+
+    // Corners from where the inbound lane meets the ring, anticlockwise on screen the way
+    // right hand traffic goes round, to where the exit lane leaves it. Rounding the corners
+    // of this polygon turns it into the ring.
+    fn around_ring(
+        &self,
+        ring: Roundabout,
+        inbound: (Point, Point),
+        exit: (Point, Point),
+    ) -> Option<Vec<Point>> {
+        let entry = ring_crossing(self.center, ring.radius, inbound.0, inbound.1)?;
+        // the exit lane is walked backwards from its end to find where it leaves the ring
+        let leave = ring_crossing(self.center, ring.radius, exit.0, (-exit.1.0, -exit.1.1))?;
+        let angle = |p: Point| (self.center.1 - p.1).atan2(p.0 - self.center.0);
+        let from = angle(entry);
+        let sweep = (angle(leave) - from).rem_euclid(TAU);
+        let steps = (sweep / FRAC_PI_6).ceil().max(1.0) as usize;
+
+        let mut corners = vec![entry];
+        for k in 1..steps {
+            let a = from + sweep * k as f64 / steps as f64;
+            corners.push((
+                self.center.0 + ring.radius * a.cos(),
+                self.center.1 - ring.radius * a.sin(),
+            ));
+        }
+        corners.push(leave);
+        Some(corners)
+    }
+}
+
+// how far a turn from heading to bearing goes, negative to the left, in -180..180
+fn turn_between(heading: f64, bearing: f64) -> f64 {
+    (bearing - heading + 180.0).rem_euclid(360.0) - 180.0
+}
+
+// the driver's right hand side when moving along d
+pub fn right_of(d: Point) -> Point {
+    (-d.1, d.0)
+}
+
+fn cross(a: Point, b: Point) -> f64 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+// where the line through p along d meets the line through q along e
+fn line_crossing(p: Point, d: Point, q: Point, e: Point) -> Option<Point> {
+    let denom = cross(d, e);
+    if denom.abs() < 1e-9 {
+        return None;
+    }
+    let t = cross((q.0 - p.0, q.1 - p.1), e) / denom;
+    Some((p.0 + d.0 * t, p.1 + d.1 * t))
+}
+
+// first point where the line from p along d enters the circle
+fn ring_crossing(center: Point, radius: f64, p: Point, d: Point) -> Option<Point> {
+    let (fx, fy) = (p.0 - center.0, p.1 - center.1);
+    let b = fx * d.0 + fy * d.1;
+    let c = fx * fx + fy * fy - radius * radius;
+    let disc = b * b - c;
+    if disc < 0.0 {
+        return None;
+    }
+    let t = -b - disc.sqrt();
+    Some((p.0 + d.0 * t, p.1 + d.1 * t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a built in layout with one line of its file swapped for another
+    fn edited(name: &str, line: &str, with: &str) -> Result<Layout, String> {
+        let (_, text) = BUILT_IN.iter().find(|(n, _)| *n == name).unwrap();
+        assert!(text.contains(line), "{} has no {:?}", name, line);
+        Layout::parse(&text.replacen(line, with, 1))
+    }
+
+    #[test]
+    fn every_built_in_layout_is_valid() {
+        for name in LAYOUT_NAMES {
+            assert!(Layout::from_arg(name).is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn numbers_that_are_not_finite_are_rejected() {
+        for (name, line, with) in [
+            ("cross", "width = 1600", "width = nan"),
+            ("cross", "lane_width = 60.0", "lane_width = inf"),
+            ("cross", "center = [800.0, 600.0]", "center = [nan, 600.0]"),
+            ("cross", "box_margin = 20.0", "box_margin = nan"),
+            ("cross", "from = \"east\"", "from = \"east\"\nbearing = nan"),
+            ("cross", "from = \"east\"", "from = \"east\"\nlength = inf"),
+            ("roundabout", "radius = 150.0", "radius = nan"),
+            ("roundabout", "radius = 150.0", "radius = inf"),
+        ] {
+            assert!(edited(name, line, with).is_err(), "{} with {}", name, with);
+        }
+    }
+
+    #[test]
+    fn a_negative_box_margin_is_rejected() {
+        assert!(edited("cross", "box_margin = 20.0", "box_margin = -200.0").is_err());
+        assert!(edited("cross", "box_margin = 20.0", "box_margin = 0.0").is_ok());
+    }
+}
@@ -0,0 +1,16 @@
+pub mod air;
+pub mod car;
+pub mod cli;
+pub mod collision;
+pub mod conflicts;
+pub mod demand;
+pub mod following;
+pub mod layout;
+pub mod network;
+pub mod pedestrian;
+pub mod policy;
+pub mod scenario;
+pub mod simulation;
+pub mod spawn_cars;
+pub mod trip;
+pub mod vehicle;
@@ -0,0 +1,317 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::car::{Direction, Lane, Movement};
+use crate::layout::{Arm, LAYOUT_NAMES, Layout, Point};
+use crate::policy::POLICY_NAMES;
+use crate::spawn_cars::TurnRadii;
+
+// an arm of an intersection in the network: (index of the intersection, side)
+pub type Approach = (usize, Direction);
+
+// Several intersections joined by roads. Every intersection is a layout placed at its
+// own centre in one shared world and runs its own policy. A link joins an arm of one
+// intersection to the arm facing it on another, both ways, so cars leaving on one
+// drive in on the other. Arms without a link are where cars enter and leave the world.
+#[derive(Debug, Clone)]
+pub struct Network {
+    pub width: f64,
+    pub height: f64,
+    pub nodes: Vec<Node>,
+    pub links: Vec<Link>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub name: String,
+    pub layout: Layout,
+    // the policy given on the command line if None
+    pub policy: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Link {
+    pub a: Approach,
+    pub b: Approach,
+    // centre to centre
+    pub length: f64,
+}
+
+// while planning: an arm, the cheapest known cost to drive in on it and the arm and
+// hop it was reached from
+type Reached = (Approach, f64, Option<(Approach, Hop)>);
+
+// one intersection on a trip: where the car drives in and what it does there
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Hop {
+    pub node: usize,
+    pub from: Direction,
+    pub movement: Movement,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NetworkFile {
+    width: f64,
+    height: f64,
+    intersections: Vec<NodeFile>,
+    #[serde(default)]
+    links: Vec<LinkFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NodeFile {
+    name: String,
+    // built in layout name, or a file next to the network file
+    layout: String,
+    center: Point,
+    policy: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LinkFile {
+    a: (String, Direction),
+    b: (String, Direction),
+}
+
+impl Network {
+    // one intersection on its own, what --layout runs
+    pub fn single(layout: Layout) -> Network {
+        Network {
+            width: layout.width,
+            height: layout.height,
+            nodes: vec![Node {
+                name: "main".to_string(),
+                layout,
+                policy: None,
+            }],
+            links: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Network, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+        Network::parse(&text, dir).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(text: &str, dir: &Path) -> Result<Network, String> {
+        let file: NetworkFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        let positive = |v: f64| v.is_finite() && v > 0.0;
+        if !positive(file.width) || !positive(file.height) {
+            return Err("width and height must be positive".to_string());
+        }
+        if file.intersections.is_empty() {
+            return Err("a network needs at least one intersection".to_string());
+        }
+
+        let mut nodes: Vec<Node> = Vec::new();
+        for n in file.intersections {
+            if nodes.iter().any(|other| other.name == n.name) {
+                return Err(format!("intersection {:?} is listed twice", n.name));
+            }
+            if let Some(name) = &n.policy
+                && !POLICY_NAMES.contains(&name.as_str())
+            {
+                return Err(format!(
+                    "intersection {:?}: unknown policy {:?}",
+                    n.name, name
+                ));
+            }
+            let mut layout = if LAYOUT_NAMES.contains(&n.layout.as_str()) {
+                Layout::from_arg(&n.layout)?
+            } else {
+                Layout::load(&dir.join(&n.layout).to_string_lossy())?
+            };
+            // placed in the shared world, arms without a link run to its edge
+            layout.width = file.width;
+            layout.height = file.height;
+            layout.center = n.center;
+            nodes.push(Node {
+                name: n.name,
+                layout,
+                policy: n.policy,
+            });
+        }
+
+        let mut links: Vec<Link> = Vec::new();
+        for l in &file.links {
+            let a = approach(&nodes, &l.a)?;
+            let b = approach(&nodes, &l.b)?;
+            if links
+                .iter()
+                .any(|k| [k.a, k.b].iter().any(|&x| x == a || x == b))
+            {
+                return Err(format!(
+                    "link {:?} - {:?}: an arm can only have one link",
+                    l.a, l.b
+                ));
+            }
+            let (pa, pb) = (nodes[a.0].layout.center, nodes[b.0].layout.center);
+            let (dx, dy) = (pb.0 - pa.0, pb.1 - pa.1);
+            let length = dx.hypot(dy);
+            // the arm of a points straight at b and the arm of b straight back
+            let da = nodes[a.0].layout.inbound(a.1);
+            let db = nodes[b.0].layout.inbound(b.1);
+            let along = -(dx * da.0 + dy * da.1);
+            let off = (dx * da.1 - dy * da.0).abs();
+            if a.0 == b.0 || along <= 0.0 || off > 1.0 || (da.0 + db.0).hypot(da.1 + db.1) > 0.01 {
+                return Err(format!(
+                    "link {:?} - {:?}: the arms do not face each other",
+                    l.a, l.b
+                ));
+            }
+            for (node, from) in [a, b] {
+                let layout = &mut nodes[node].layout;
+                let arm = layout.arms.iter_mut().find(|arm| arm.from == from);
+                arm.expect("checked by approach").length = Some(length / 2.0);
+            }
+            links.push(Link { a, b, length });
+        }
+
+        for node in &nodes {
+            node.layout
+                .validate()
+                .map_err(|e| format!("intersection {:?}: {}", node.name, e))?;
+        }
+        Ok(Network {
+            width: file.width,
+            height: file.height,
+            nodes,
+            links,
+        })
+    }
+
+    // the arm at the other end of the link, None at the edge of the world
+    pub fn linked(&self, at: Approach) -> Option<Approach> {
+        self.links.iter().find_map(|link| match at {
+            _ if link.a == at => Some(link.b),
+            _ if link.b == at => Some(link.a),
+            _ => None,
+        })
+    }
+
+    // arms cars come into the world on
+    pub fn origins(&self) -> Vec<Approach> {
+        self.open_arms(|arm| !arm.lanes.is_empty())
+    }
+
+    // arms cars leave the world on
+    pub fn exits(&self) -> Vec<Approach> {
+        self.open_arms(|arm| arm.exit_lanes > 0)
+    }
+
+    fn open_arms(&self, keep: impl Fn(&Arm) -> bool) -> Vec<Approach> {
+        let mut arms = Vec::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            for arm in &node.layout.arms {
+                if keep(arm) && self.linked((i, arm.from)).is_none() {
+                    arms.push((i, arm.from));
+                }
+            }
+        }
+        arms
+    }
+
+    // the arm a hop leaves its intersection on
+    pub fn exit_of(&self, hop: Hop) -> Option<Approach> {
+        let layout = &self.nodes[hop.node].layout;
+        let exit = layout.exit_arm(layout.arm(hop.from)?, hop.movement)?;
+        Some((hop.node, exit.from))
+    }
+
+    // Shortest trip from an arm at the edge of the world to an exit, by the length of the
+    // routes driven. Dijkstra over the arms cars drive in on; the graph is small, so the
+    // next arm is found by a plain scan, which also keeps ties in a fixed order.
+    pub fn plan(
+        &self,
+        origin: Approach,
+        destination: Approach,
+        radii: &TurnRadii,
+    ) -> Option<Vec<Hop>> {
+        let mut best: Vec<Reached> = vec![(origin, 0.0, None)];
+        let mut done: Vec<Approach> = Vec::new();
+        let mut arrival: Option<(f64, Approach, Hop)> = None;
+
+        while let Some(&(at, cost, _)) = best
+            .iter()
+            .filter(|(a, _, _)| !done.contains(a))
+            .min_by(|x, y| x.1.total_cmp(&y.1))
+        {
+            if arrival.is_some_and(|(total, _, _)| total <= cost) {
+                break;
+            }
+            done.push(at);
+            for movement in [Movement::Left, Movement::Straight, Movement::Right] {
+                let hop = Hop {
+                    node: at.0,
+                    from: at.1,
+                    movement,
+                };
+                let (Some(length), Some(out)) = (self.hop_length(hop, radii), self.exit_of(hop))
+                else {
+                    continue;
+                };
+                let total = cost + length;
+                if out == destination && arrival.is_none_or(|(t, _, _)| total < t) {
+                    arrival = Some((total, at, hop));
+                }
+                let Some(next) = self.linked(out) else {
+                    continue;
+                };
+                match best.iter_mut().find(|(a, _, _)| *a == next) {
+                    Some(entry) if total < entry.1 => *entry = (next, total, Some((at, hop))),
+                    Some(_) => {}
+                    None => best.push((next, total, Some((at, hop)))),
+                }
+            }
+        }
+
+        let (_, mut at, last) = arrival?;
+        let mut hops = vec![last];
+        while let Some(&(_, _, Some((prev, hop)))) = best.iter().find(|(a, _, _)| *a == at) {
+            hops.push(hop);
+            at = prev;
+        }
+        hops.reverse();
+        Some(hops)
+    }
+
+    // length of the route for the movement, through the first lane that allows it
+    fn hop_length(&self, hop: Hop, radii: &TurnRadii) -> Option<f64> {
+        let layout = &self.nodes[hop.node].layout;
+        let arm = layout.arm(hop.from)?;
+        let index = arm
+            .lanes
+            .iter()
+            .position(|lane| lane.movements.contains(&hop.movement))?;
+        let lane = Lane {
+            index,
+            movement: hop.movement,
+        };
+        let (mut from, waypoints) = layout.route(hop.from, lane, radii)?;
+        let mut length = 0.0;
+        for w in waypoints {
+            length += (w.x - from.0).hypot(w.y - from.1);
+            from = (w.x, w.y);
+        }
+        Some(length)
+    }
+}
+
+fn approach(nodes: &[Node], (name, from): &(String, Direction)) -> Result<Approach, String> {
+    let i = nodes
+        .iter()
+        .position(|n| &n.name == name)
+        .ok_or(format!("link to unknown intersection {:?}", name))?;
+    match nodes[i].layout.arm(*from) {
+        Some(_) => Ok((i, *from)),
+        None => Err(format!(
+            "intersection {:?} has no arm from {:?}",
+            name, from
+        )),
+    }
+}
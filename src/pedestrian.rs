@@ -0,0 +1,235 @@
+use rand::Rng;
+
+use crate::car::{Area, Car, Direction};
+use crate::collision::Obb;
+use crate::conflicts::segment_distance;
+use crate::layout::{Arm, Layout, Point, right_of};
+use crate::network::Network;
+
+// px/s, a brisk walk, quicker than life so a crossing does not hold the road for long
+pub const WALK_SPEED: f64 = 40.0;
+// a pedestrian is drawn and checked for collisions as a square this wide
+pub const PEDESTRIAN_SIZE: f64 = 12.0;
+
+// Where pedestrians cross an arm: a band across the road at the mouth of the arm, in
+// the clear margin of the box, so cars held at their line stand short of it and every
+// car driving in or out on that arm crosses it.
+#[derive(Debug, Clone)]
+pub struct Crosswalk {
+    // intersection of the network and the arm it crosses
+    pub node: usize,
+    pub from: Direction,
+    // box of the intersection, the crosswalk lies along its edge
+    pub intersection: Area,
+    // kerb to kerb along the middle of the band, the kerbs are off the road
+    pub ends: (Point, Point),
+    pub width: f64,
+}
+
+impl Crosswalk {
+    pub fn length(&self) -> f64 {
+        distance(self.ends.0, self.ends.1)
+    }
+
+    fn band(&self) -> Obb {
+        let (a, b) = self.ends;
+        let length = self.length().max(1e-9);
+        let along = ((b.0 - a.0) / length, (b.1 - a.1) / length);
+        Obb {
+            center: ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0),
+            axes: [along, (-along.1, along.0)],
+            half_extents: (length / 2.0, self.width / 2.0),
+        }
+    }
+
+    // some of the car's body is on the band
+    pub fn covers(&self, car: &Car) -> bool {
+        self.band().overlaps(&Obb::of(car))
+    }
+
+    // The car stands on the crosswalk, or the rest of its route runs over it. A car
+    // whose route only passes close by counts when its body would reach the band, a
+    // corner of it swings out this far on a turn.
+    pub fn in_path(&self, car: &Car) -> bool {
+        if car.node != self.node {
+            return false;
+        }
+        if self.covers(car) {
+            return true;
+        }
+        if car.has_left_box() {
+            return false;
+        }
+        let reach = (self.width + car.length().hypot(car.width())) / 2.0;
+        let mut from = car.position;
+        car.waypoints.iter().any(|w| {
+            let to = (w.x, w.y);
+            let near = segment_distance((from, to), self.ends) < reach;
+            from = to;
+            near
+        })
+    }
+}
+
+// One crosswalk across every arm of every intersection that has a margin wide enough
+// for a pedestrian to stand in.
+pub fn crosswalks(network: &Network) -> Vec<Crosswalk> {
+    let mut crosswalks = Vec::new();
+    for (node, n) in network.nodes.iter().enumerate() {
+        let layout = &n.layout;
+        if layout.box_margin < PEDESTRIAN_SIZE {
+            continue;
+        }
+        for arm in &layout.arms {
+            let width = layout.lane_width_of(arm);
+            let kerb = PEDESTRIAN_SIZE / 2.0;
+            let left = -(arm.exit_lanes as f64 * width) - kerb;
+            let right = arm.lanes.len() as f64 * width + kerb;
+            // each kerb in the middle of the margin, the crosswalk follows the edge of
+            // the box even where it runs skew to the arm
+            let end = |offset| {
+                let along = reach_at(layout, arm.from, offset) - layout.box_margin / 2.0;
+                layout.point_on(arm.from, offset, along)
+            };
+            let ends = (end(left), end(right));
+            // where the roads still overlap at the box there is no kerb to wait on
+            if [ends.0, ends.1].iter().any(|&p| {
+                layout
+                    .arms
+                    .iter()
+                    .any(|other| other.from != arm.from && on_road(layout, other, p))
+            }) {
+                continue;
+            }
+            crosswalks.push(Crosswalk {
+                node,
+                from: arm.from,
+                intersection: layout.intersection(),
+                ends,
+                width: layout.box_margin,
+            });
+        }
+    }
+    crosswalks
+}
+
+// How far out along the arm the box reaches `offset` px right of its centre line. On a
+// square arm that is the same right across the road, on a slanted one the edge of the
+// box runs skew to it and the lanes on one side stop nearer the centre.
+fn reach_at(layout: &Layout, from: Direction, offset: f64) -> f64 {
+    let area = layout.intersection();
+    let half = (
+        (area.0.end() - area.0.start()) / 2.0,
+        (area.1.end() - area.1.start()) / 2.0,
+    );
+    let d = layout.inbound(from);
+    let r = right_of(d);
+    let limit = |d: f64, r: f64, half: f64| {
+        if d == 0.0 {
+            f64::INFINITY
+        } else {
+            (half + d.signum() * r * offset) / d.abs()
+        }
+    };
+    limit(d.0, r.0, half.0).min(limit(d.1, r.1, half.1))
+}
+
+// the point lies on the arm's road, between its kerbs and out from the centre
+fn on_road(layout: &Layout, arm: &Arm, p: Point) -> bool {
+    let d = layout.inbound(arm.from);
+    let r = right_of(d);
+    let (x, y) = (p.0 - layout.center.0, p.1 - layout.center.1);
+    let width = layout.lane_width_of(arm);
+    let offset = x * r.0 + y * r.1;
+    -(x * d.0 + y * d.1) > 0.0
+        && offset > -(arm.exit_lanes as f64 * width)
+        && offset < arm.lanes.len() as f64 * width
+}
+
+#[derive(Debug, Clone)]
+pub struct Pedestrian {
+    pub id: usize,
+    pub crosswalk: Crosswalk,
+    pub position: Point,
+    // the kerb on the other side
+    pub target: Point,
+    // let across by the intersection, it no longer waits
+    pub walking: bool,
+    // when it came to the kerb
+    pub arrived: f64,
+}
+
+impl Pedestrian {
+    // a pedestrian at one of the two kerbs of the crosswalk, picked at random
+    pub fn new(id: usize, crosswalk: Crosswalk, time: f64, rng: &mut impl Rng) -> Self {
+        let (a, b) = crosswalk.ends;
+        let (position, target) = if rng.gen_bool(0.5) { (a, b) } else { (b, a) };
+        Pedestrian {
+            id,
+            crosswalk,
+            position,
+            target,
+            walking: false,
+            arrived: time,
+        }
+    }
+
+    // seconds the rest of the way across takes
+    pub fn time_to_cross(&self) -> f64 {
+        distance(self.position, self.target) / WALK_SPEED
+    }
+
+    // where it is t seconds on, walking straight across
+    pub fn ahead(&self, t: f64) -> Point {
+        let left = distance(self.position, self.target);
+        let k = (WALK_SPEED * t / left.max(1e-9)).min(1.0);
+        (
+            self.position.0 + (self.target.0 - self.position.0) * k,
+            self.position.1 + (self.target.1 - self.position.1) * k,
+        )
+    }
+
+    // A step across if it is walking, true once it has reached the other kerb. A car
+    // that came to a stop on the crosswalk after all holds it up, it does not walk into
+    // the car.
+    pub fn step(&mut self, cars: &[Car], dt: f64) -> bool {
+        if self.walking {
+            let next = self.ahead(dt);
+            let body = body_at(next);
+            if !cars
+                .iter()
+                .any(|c| c.node == self.crosswalk.node && body.overlaps(&Obb::of(c)))
+            {
+                self.position = next;
+            }
+        }
+        self.walking && distance(self.position, self.target) < 1e-6
+    }
+
+    // Others walk across the same crosswalk and are not halfway yet. The pedestrian
+    // goes along with them, the cars are held for them anyway.
+    pub fn joins(&self, walkers: &[Pedestrian]) -> bool {
+        walkers.iter().any(|w| {
+            w.walking
+                && w.crosswalk.node == self.crosswalk.node
+                && w.crosswalk.from == self.crosswalk.from
+                && w.time_to_cross() > self.time_to_cross() / 2.0
+        })
+    }
+
+    pub fn body(&self) -> Obb {
+        body_at(self.position)
+    }
+}
+
+fn body_at(center: Point) -> Obb {
+    Obb {
+        center,
+        axes: [(1.0, 0.0), (0.0, 1.0)],
+        half_extents: (PEDESTRIAN_SIZE / 2.0, PEDESTRIAN_SIZE / 2.0),
+    }
+}
+
+fn distance(a: Point, b: Point) -> f64 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use crate::car::{Car, Direction, Lane};
+use crate::pedestrian::Pedestrian;
+use crate::policy::{self, Access, Crossings, IntersectionPolicy, PolicyContext};
+
+// Releases waiting cars in batches. Once the box is clear, the waiting cars are taken
+// in arrival order and each one joins the batch if it conflicts with nobody already
+// in it. While the batch crosses, cars reaching the line behind a batch member in the
+// same lane join it as a platoon, up to `max_platoon` cars per lane. Pedestrians join
+// batches too, in their turn, and conflict with the cars driving over their crosswalk.
+pub struct BatchPolicy {
+    max_platoon: usize,
+    platoons: HashMap<(Direction, Lane), usize>,
+    crossings: Crossings,
+}
+
+// a car or a pedestrian waiting for the next batch
+enum Waiting<'a> {
+    Car(&'a Car),
+    Walker(&'a Pedestrian),
+}
+
+impl Default for BatchPolicy {
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+impl BatchPolicy {
+    pub fn new(max_platoon: usize) -> Self {
+        BatchPolicy {
+            max_platoon,
+            platoons: HashMap::new(),
+            crossings: Crossings::default(),
+        }
+    }
+}
+
+impl IntersectionPolicy for BatchPolicy {
+    fn name(&self) -> &'static str {
+        "batch"
+    }
+
+    fn update(&mut self, cars: &[Car], ctx: &PolicyContext) {
+        self.crossings.update(cars, ctx.walkers);
+        // a platoon stuck behind a member held back would keep the batch going forever
+        self.crossings.withdraw_blocked(cars);
+        // a car held on a crosswalk, or in the box on its way to one, has to drive on
+        // before anyone crosses there, the pedestrian waits for a batch of its own
+        for walker in ctx.walkers.iter().filter(|w| !w.walking) {
+            if cars.iter().any(|c| {
+                self.crossings.is_waiting(c.id)
+                    && walker.crosswalk.in_path(c)
+                    && !policy::stops_short(c, &walker.crosswalk)
+            }) {
+                self.crossings.withdraw_walker(walker.id);
+            }
+        }
+        let walkers: Vec<&Pedestrian> = ctx
+            .walkers
+            .iter()
+            .filter(|w| w.walking || self.crossings.is_walker_committed(w.id))
+            .collect();
+
+        if cars.iter().any(|c| self.crossings.is_active(c.id)) || !walkers.is_empty() {
+            // the running batch takes followers of its platoons, and pedestrians
+            // joining those on their way across
+            for walker in ctx.walkers {
+                if walker.joins(ctx.walkers) {
+                    self.crossings.commit_walker(walker.id);
+                }
+            }
+            for car in self.crossings.waiting(cars) {
+                if !self.crossings.is_blocked(car, cars)
+                    && !walkers.iter().any(|w| w.crosswalk.in_path(car))
+                    && let Some(count) = self.platoons.get_mut(&(car.direction, car.lane))
+                    && *count < self.max_platoon
+                {
+                    *count += 1;
+                    self.crossings.commit(car.id);
+                }
+            }
+            return;
+        }
+
+        // box is clear, form the next batch
+        self.platoons.clear();
+        let mut waiting: Vec<(u64, Waiting)> = self
+            .crossings
+            .waiting(cars)
+            .into_iter()
+            .filter_map(|c| Some((self.crossings.arrival(c.id)?, Waiting::Car(c))))
+            .collect();
+        waiting.extend(
+            ctx.walkers
+                .iter()
+                .filter_map(|w| Some((self.crossings.walker_arrival(w.id)?, Waiting::Walker(w)))),
+        );
+        // a car stopped past its line, on the crosswalk or with its nose in the box, has
+        // to get out of the way first, whoever came before it
+        waiting.sort_by_key(|(arrival, member)| {
+            let past_line = matches!(member, Waiting::Car(c)
+                if c.distance_to_stop_line().is_none_or(|line| line < 0.0));
+            (!past_line, *arrival)
+        });
+
+        let mut batch: Vec<&Car> = Vec::new();
+        let mut walkers: Vec<&Pedestrian> = Vec::new();
+        for (_, member) in waiting {
+            match member {
+                // a car behind a waiting car of another route cannot go anyway
+                Waiting::Car(car) => {
+                    if !self.crossings.is_blocked(car, cars)
+                        && batch.iter().all(|b| !ctx.conflicts.between(car, b))
+                        && walkers.iter().all(|w| !w.crosswalk.in_path(car))
+                    {
+                        batch.push(car);
+                    }
+                }
+                Waiting::Walker(walker) => {
+                    if batch.iter().all(|c| !walker.crosswalk.in_path(c)) {
+                        walkers.push(walker);
+                    }
+                }
+            }
+        }
+        for car in batch {
+            let count = self.platoons.entry((car.direction, car.lane)).or_insert(0);
+            if *count < self.max_platoon {
+                *count += 1;
+                self.crossings.commit(car.id);
+            }
+        }
+        for walker in walkers {
+            self.crossings.commit_walker(walker.id);
+        }
+    }
+
+    fn access(&mut self, car: &Car, _cars: &[Car], _ctx: &PolicyContext) -> Access {
+        if self.crossings.is_waiting(car.id) {
+            Access::Stop
+        } else {
+            Access::Go(car.cruise_speed())
+        }
+    }
+
+    fn hold(&mut self, car: &Car) {
+        self.crossings.withdraw(car.id);
+    }
+
+    fn walk(&mut self, walker: &Pedestrian, cars: &[Car], _ctx: &PolicyContext) -> bool {
+        self.crossings.is_walker_committed(walker.id) && policy::crosswalk_clear(walker, cars)
+    }
+}
@@ -0,0 +1,60 @@
+use crate::car::Car;
+use crate::pedestrian::Pedestrian;
+use crate::policy::{self, Access, Crossings, IntersectionPolicy, PolicyContext};
+
+// First come first served: a car at the line goes once no car that reached the
+// line before it and conflicts with it is still on its way through the box.
+// Pedestrians queue up the same way: a car waits for one that came to a crosswalk in
+// its path first or is walking across, and a pedestrian for the cars that came first
+// unless it can join others already on their way across.
+#[derive(Default)]
+pub struct FifoPolicy {
+    crossings: Crossings,
+}
+
+impl IntersectionPolicy for FifoPolicy {
+    fn name(&self) -> &'static str {
+        "fifo"
+    }
+
+    fn update(&mut self, cars: &[Car], ctx: &PolicyContext) {
+        self.crossings.update(cars, ctx.walkers);
+    }
+
+    fn access(&mut self, car: &Car, cars: &[Car], ctx: &PolicyContext) -> Access {
+        if !self.crossings.is_waiting(car.id) {
+            return Access::Go(car.cruise_speed());
+        }
+
+        let arrival = self.crossings.arrival(car.id);
+        let blocked = cars.iter().any(|c| {
+            ctx.conflicts.between(car, c)
+                && (self.crossings.is_active(c.id)
+                    || (self.crossings.is_waiting(c.id) && self.crossings.arrival(c.id) < arrival))
+        });
+        let walkers = ctx.walkers.iter().any(|w| {
+            w.crosswalk.in_path(car) && (w.walking || self.crossings.walker_arrival(w.id) < arrival)
+        });
+        if blocked || walkers {
+            return Access::Stop;
+        }
+
+        self.crossings.commit(car.id);
+        Access::Go(car.cruise_speed())
+    }
+
+    fn hold(&mut self, car: &Car) {
+        self.crossings.withdraw(car.id);
+    }
+
+    fn walk(&mut self, walker: &Pedestrian, cars: &[Car], ctx: &PolicyContext) -> bool {
+        let arrival = self.crossings.walker_arrival(walker.id);
+        // a car let through but queued behind one still waiting goes after that one
+        let blocked = cars.iter().any(|c| {
+            walker.crosswalk.in_path(c)
+                && ((self.crossings.is_active(c.id) && !self.crossings.is_blocked(c, cars))
+                    || (self.crossings.is_waiting(c.id) && self.crossings.arrival(c.id) < arrival))
+        });
+        (!blocked || walker.joins(ctx.walkers)) && policy::crosswalk_clear(walker, cars)
+    }
+}
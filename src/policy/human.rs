@@ -0,0 +1,104 @@
+use crate::car::Car;
+use crate::policy::{
+    self, APPROACH_DISTANCE, Access, IntersectionPolicy, PolicyContext, yield_entry,
+};
+use crate::simulation::DT;
+
+// seconds a human driver takes to act on what it sees
+pub const REACTION_TIME: f64 = 0.8;
+// seconds a car on a crossing route has to be from the box before a human driver
+// takes the gap in front of it
+const ACCEPTED_GAP: f64 = 3.0;
+
+// A human driver does not talk to the intersection manager. It drives up to its line,
+// looks, and goes once the gap has been open for its reaction time: no car on a crossing
+// route is in the box or due there within ACCEPTED_GAP, no other human driver about to
+// cross came first and nobody walks across a crosswalk it drives over. One that only
+// waits at the kerb it does not wait for, the policy does not know to keep the car back
+// and would hold the pedestrian behind it for good. A traffic light it can see, and it
+// stops on red. On a roundabout it gives way to the ring like under the `yield` policy.
+// Once it could not stop at its line any more it drives on, gap or not.
+pub fn human_access(
+    car: &mut Car,
+    cars: &[Car],
+    policy: &dyn IntersectionPolicy,
+    ctx: &PolicyContext,
+) -> Access {
+    // a driver queued behind another car has no gap of its own to watch yet
+    let open =
+        policy::at_head(car, cars) && !policy.red_for(car, ctx) && gap_open(car, cars, policy, ctx);
+    car.gap_seen = if open { car.gap_seen + DT } else { 0.0 };
+    if car.occupies_box()
+        || car.has_left_box()
+        || car.distance_to_box() > APPROACH_DISTANCE
+        || !policy::can_stop(car)
+        || car.gap_seen >= REACTION_TIME
+    {
+        Access::Go(car.cruise_speed())
+    } else {
+        Access::Stop
+    }
+}
+
+fn gap_open(car: &Car, cars: &[Car], policy: &dyn IntersectionPolicy, ctx: &PolicyContext) -> bool {
+    if policy::walking_across(car, ctx.walkers) {
+        return false;
+    }
+    if car.ring.is_some() {
+        return !yield_entry::gives_way_on_ring(car, cars.iter());
+    }
+    !cars.iter().any(|c| {
+        crosses(car, c, ctx)
+            && (c.occupies_box()
+                || (c.speed > 1.0 && c.distance_to_box() < c.speed * ACCEPTED_GAP)
+                || (c.human
+                    && (c.id < car.id || c.gap_seen >= REACTION_TIME)
+                    && policy::at_head(c, cars)
+                    && !policy.red_for(c, ctx)))
+    })
+}
+
+// The automated car stays at its line for a human driver on a crossing route that is
+// on its way across: it took its gap, is in the box or too close to stop. On a
+// roundabout it gives way to a human driver on the ring as under the `yield` policy.
+// The manager cannot tell either to wait, so whatever the policy said the car gives
+// way. Returns None when there is no such human driver and the policy decides.
+pub fn give_way_to_humans(car: &Car, cars: &[Car], ctx: &PolicyContext) -> Option<Access> {
+    if car.human
+        || car.occupies_box()
+        || car.has_left_box()
+        || car.distance_to_box() > APPROACH_DISTANCE
+    {
+        return None;
+    }
+    let yields = match car.ring {
+        Some(_) => yield_entry::gives_way_on_ring(car, cars.iter().filter(|c| c.human)),
+        None => {
+            policy::can_stop(car)
+                && cars
+                    .iter()
+                    .any(|c| c.human && crosses(car, c, ctx) && crossing(c, cars))
+        }
+    };
+    yields.then_some(Access::Stop)
+}
+
+// a human driver waits at its line, or drives up to it, on a route crossing that of
+// the car, the manager keeps its way clear
+pub fn human_waits(car: &Car, cars: &[Car], ctx: &PolicyContext) -> bool {
+    cars.iter()
+        .any(|c| c.human && crosses(car, c, ctx) && policy::at_head(c, cars))
+}
+
+fn crosses(car: &Car, other: &Car, ctx: &PolicyContext) -> bool {
+    other.id != car.id
+        && !other.has_left_box()
+        && ctx.conflicts.between(car, other)
+        && !car.shares_lane(other)
+}
+
+// a human driver first in its lane set off across the box
+fn crossing(car: &Car, cars: &[Car]) -> bool {
+    car.occupies_box()
+        || (policy::at_head(car, cars) && (car.gap_seen >= REACTION_TIME || !policy::can_stop(car)))
+}
@@ -0,0 +1,313 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::car::Car;
+use crate::conflicts::ConflictTable;
+use crate::layout::Layout;
+use crate::pedestrian::{Crosswalk, Pedestrian};
+
+mod batch;
+mod fifo;
+mod human;
+mod preemption;
+mod priority;
+mod reservation;
+mod traffic_light;
+mod yield_entry;
+
+pub use batch::BatchPolicy;
+pub use fifo::FifoPolicy;
+pub use human::{REACTION_TIME, give_way_to_humans, human_access, human_waits};
+pub use preemption::preempt;
+pub use priority::PriorityPolicy;
+pub use reservation::ReservationPolicy;
+pub use traffic_light::TrafficLightPolicy;
+pub use yield_entry::YieldPolicy;
+
+// how close to the box a car has to be before the policy decides about it,
+// far enough out for a car at road speed to brake to its stop line
+pub const APPROACH_DISTANCE: f64 = 250.0;
+// seconds a car on its approach gives way to pedestrians as they come, after that
+// the ones who came to the kerb later wait for it, see `waits_for_cars`
+const PATIENCE: f64 = 10.0;
+// a car on the road out slower than this, in px/s, is the end of a queue
+const QUEUE_SPEED: f64 = 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Stop,
+    // go on at this speed in px/s
+    Go(f64),
+}
+
+// what a policy sees of the world besides the cars
+pub struct PolicyContext<'a> {
+    pub time: f64,
+    pub conflicts: &'a ConflictTable,
+    // pedestrians at the crosswalks of the intersection, waiting or walking
+    pub walkers: &'a [Pedestrian],
+}
+
+// Decides which cars may cross the intersection box and how fast. Planes never ask.
+// Implement this to try a new crossing algorithm, then add it to `from_name`.
+pub trait IntersectionPolicy {
+    fn name(&self) -> &'static str;
+
+    // called once per tick with every car before any of them move
+    fn update(&mut self, _cars: &[Car], _ctx: &PolicyContext) {}
+
+    fn access(&mut self, car: &Car, cars: &[Car], ctx: &PolicyContext) -> Access;
+
+    // the car was held at its line to let an emergency vehicle through, whatever the
+    // policy let it do before is void and it asks again once the way is clear
+    fn hold(&mut self, _car: &Car) {}
+
+    // A pedestrian waits at the kerb of a crosswalk, true lets it walk across now. From
+    // then on the policy keeps every car off that crosswalk until it is across. Left as
+    // it is, a pedestrian goes as soon as the crosswalk is clear and cars give way to
+    // it like at a zebra crossing, see `yields_to_walkers`.
+    fn walk(&mut self, walker: &Pedestrian, cars: &[Car], ctx: &PolicyContext) -> bool {
+        crosswalk_clear(walker, cars) && !waits_for_cars(walker, cars, ctx)
+    }
+
+    // A signal shows the car red. It is all a human driver, who never asks the policy,
+    // gets to see of it; without signals there is nothing to see.
+    fn red_for(&self, _car: &Car, _ctx: &PolicyContext) -> bool {
+        false
+    }
+}
+
+pub const POLICY_NAMES: [&str; 6] = [
+    "priority",
+    "reservation",
+    "fifo",
+    "lights",
+    "batch",
+    "yield",
+];
+
+// the policy of that name for an intersection of the layout
+pub fn from_name(name: &str, layout: &Layout) -> Option<Box<dyn IntersectionPolicy>> {
+    match name {
+        "priority" => Some(Box::new(PriorityPolicy)),
+        "reservation" => Some(Box::new(ReservationPolicy::default())),
+        "fifo" => Some(Box::new(FifoPolicy::default())),
+        "lights" => Some(Box::new(TrafficLightPolicy::new(layout, 8.0, 2.0, 12.0))),
+        "batch" => Some(Box::new(BatchPolicy::default())),
+        "yield" => Some(Box::new(YieldPolicy)),
+        _ => None,
+    }
+}
+
+// the car can still come to a halt at its stop line, or already stands there
+pub fn can_stop(car: &Car) -> bool {
+    car.distance_to_stop_line()
+        .is_some_and(|line| line.max(0.0) >= car.stopping_distance())
+}
+
+// The car can still halt short of the crosswalk: it is not on it, not in the box on its
+// way out over it, and not driving up too fast to stop at its line. A car stopped past
+// its line with its nose on the crosswalk has to drive on first.
+pub fn stops_short(car: &Car, crosswalk: &Crosswalk) -> bool {
+    !crosswalk.covers(car) && !car.occupies_box() && !car.has_left_box() && can_stop(car)
+}
+
+// nothing keeps a pedestrian from starting across
+pub fn crosswalk_clear(walker: &Pedestrian, cars: &[Car]) -> bool {
+    cars.iter()
+        .all(|c| !walker.crosswalk.in_path(c) || stops_short(c, &walker.crosswalk))
+}
+
+// A pedestrian walks across a crosswalk the car has still to drive over. One stopped
+// with its nose on the crosswalk is in the pedestrian's way, it drives on first.
+pub fn walking_across(car: &Car, walkers: &[Pedestrian]) -> bool {
+    !car.occupies_box()
+        && !car.has_left_box()
+        && walkers
+            .iter()
+            .any(|w| w.walking && w.crosswalk.in_path(car) && !w.crosswalk.covers(car))
+}
+
+// The car stays behind its line for pedestrians: one walks across a crosswalk in its
+// path, or waits at one and the car can still stop for it.
+pub fn yields_to_walkers(car: &Car, cars: &[Car], ctx: &PolicyContext) -> bool {
+    walking_across(car, ctx.walkers)
+        || ctx.walkers.iter().any(|w| {
+            w.crosswalk.in_path(car)
+                && stops_short(car, &w.crosswalk)
+                && !waits_for_cars(w, cars, ctx)
+        })
+}
+
+// Pedestrians that keep coming would hold the cars at a zebra crossing for good. Once a
+// car has been on its approach past PATIENCE, a pedestrian who came to the kerb after it
+// did waits, and cars no longer wait for it, until no such car is left.
+pub fn waits_for_cars(walker: &Pedestrian, cars: &[Car], ctx: &PolicyContext) -> bool {
+    cars.iter()
+        .any(|c| c.approaching > PATIENCE && ctx.time - c.approaching < walker.arrived)
+}
+
+// first in its lane to the line, nothing waits in front of it
+pub fn at_head(car: &Car, cars: &[Car]) -> bool {
+    car.distance_to_box() <= APPROACH_DISTANCE
+        && car
+            .car_in_front(cars)
+            .is_none_or(|front| front.occupies_box() || front.has_left_box())
+}
+
+// The car would come to a stand in the box: its route goes on to another intersection
+// and the queue on the road there, with the cars ahead of it on their way to the end
+// of that queue, leaves no room for it past the box. One that can still stop waits at
+// its line, or at the entry of a roundabout, so a queue running back from the next
+// intersection never blocks this one.
+pub fn exit_blocked(car: &Car, cars: &[Car]) -> bool {
+    if car.trip.is_empty() || car.occupies_box() || car.has_left_box() || !can_stop(car) {
+        return false;
+    }
+    let out: Vec<&Car> = cars
+        .iter()
+        .filter(|c| c.id != car.id && car.exits_with(c))
+        .collect();
+    // how far past the box the rear of the queue is
+    let room = out
+        .iter()
+        .filter(|c| c.has_left_box() && c.speed < QUEUE_SPEED)
+        .map(|c| c.distance_to_box() - c.length() / 2.0)
+        .fold(f64::INFINITY, f64::min);
+    let taken: f64 = out
+        .iter()
+        .filter(|c| {
+            if c.has_left_box() {
+                c.distance_to_box() - c.length() / 2.0 < room && c.speed >= QUEUE_SPEED
+            } else {
+                c.occupies_box() || !can_stop(c)
+            }
+        })
+        .map(|c| c.length() + c.idm.min_gap)
+        .sum();
+    taken + car.length() + car.idm.min_gap > room
+}
+
+// Shared bookkeeping for policies that decide once at the stop line: the order cars
+// reached the line, which of them were let through and which have left the box again.
+// Pedestrians at the crosswalks take their place in the same order.
+#[derive(Default)]
+pub struct Crossings {
+    next_arrival: u64,
+    arrivals: HashMap<usize, u64>,
+    committed: HashSet<usize>,
+    entered: HashSet<usize>,
+    exited: HashSet<usize>,
+    // pedestrians have ids of their own
+    walker_arrivals: HashMap<usize, u64>,
+    walkers_committed: HashSet<usize>,
+}
+
+impl Crossings {
+    pub fn update(&mut self, cars: &[Car], walkers: &[Pedestrian]) {
+        for car in cars {
+            if car.in_intersection() {
+                self.entered.insert(car.id);
+            } else if self.entered.contains(&car.id) || car.has_left_box() {
+                // a long vehicle only leaves once its tail is out as well, and one turning
+                // off round a corner of the box may never have had its centre in it
+                self.entered.insert(car.id);
+                if !car.occupies_box() {
+                    self.exited.insert(car.id);
+                }
+            } else if car.distance_to_box() <= APPROACH_DISTANCE
+                && !self.arrivals.contains_key(&car.id)
+            {
+                self.arrivals.insert(car.id, self.next_arrival);
+                self.next_arrival += 1;
+            }
+        }
+
+        let alive: HashSet<usize> = cars.iter().map(|c| c.id).collect();
+        self.arrivals.retain(|id, _| alive.contains(id));
+        self.committed.retain(|id| alive.contains(id));
+        self.entered.retain(|id| alive.contains(id));
+        self.exited.retain(|id| alive.contains(id));
+
+        for walker in walkers {
+            if !self.walker_arrivals.contains_key(&walker.id) {
+                self.walker_arrivals.insert(walker.id, self.next_arrival);
+                self.next_arrival += 1;
+            }
+        }
+        let alive: HashSet<usize> = walkers.iter().map(|w| w.id).collect();
+        self.walker_arrivals.retain(|id, _| alive.contains(id));
+        self.walkers_committed.retain(|id| alive.contains(id));
+    }
+
+    // the car stands at the line and has not been let through yet
+    pub fn is_waiting(&self, id: usize) -> bool {
+        self.arrivals.contains_key(&id)
+            && !self.committed.contains(&id)
+            && !self.entered.contains(&id)
+    }
+
+    // the car was let through (or is inside) and has not left the box yet
+    pub fn is_active(&self, id: usize) -> bool {
+        (self.committed.contains(&id) || self.entered.contains(&id)) && !self.exited.contains(&id)
+    }
+
+    pub fn commit(&mut self, id: usize) {
+        self.committed.insert(id);
+    }
+
+    // takes back the go of a car that has not entered the box, it waits again
+    pub fn withdraw(&mut self, id: usize) {
+        if !self.entered.contains(&id) {
+            self.committed.remove(&id);
+        }
+    }
+
+    // takes back the go of cars let through behind one that was held again, they
+    // cannot get past it and wait with it
+    pub fn withdraw_blocked(&mut self, cars: &[Car]) {
+        let held: Vec<usize> = cars
+            .iter()
+            .filter(|c| self.committed.contains(&c.id) && !self.entered.contains(&c.id))
+            .filter(|c| self.is_blocked(c, cars))
+            .map(|c| c.id)
+            .collect();
+        for id in held {
+            self.committed.remove(&id);
+        }
+    }
+
+    pub fn arrival(&self, id: usize) -> Option<u64> {
+        self.arrivals.get(&id).copied()
+    }
+
+    pub fn walker_arrival(&self, id: usize) -> Option<u64> {
+        self.walker_arrivals.get(&id).copied()
+    }
+
+    // the pedestrian was let across, it may not have set off yet
+    pub fn commit_walker(&mut self, id: usize) {
+        self.walkers_committed.insert(id);
+    }
+
+    // takes back the go of a pedestrian who has not set off yet, it waits again
+    pub fn withdraw_walker(&mut self, id: usize) {
+        self.walkers_committed.remove(&id);
+    }
+
+    pub fn is_walker_committed(&self, id: usize) -> bool {
+        self.walkers_committed.contains(&id)
+    }
+
+    // the car is queued behind a car of its lane the policy has not let through yet
+    pub fn is_blocked(&self, car: &Car, cars: &[Car]) -> bool {
+        car.car_in_front(cars)
+            .is_some_and(|front| self.is_waiting(front.id))
+    }
+
+    // waiting cars, first arrived first
+    pub fn waiting<'a>(&self, cars: &'a [Car]) -> Vec<&'a Car> {
+        let mut waiting: Vec<&Car> = cars.iter().filter(|c| self.is_waiting(c.id)).collect();
+        waiting.sort_by_key(|c| self.arrivals[&c.id]);
+        waiting
+    }
+}
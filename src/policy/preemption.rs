@@ -0,0 +1,81 @@
+use crate::car::Car;
+use crate::policy::{self, APPROACH_DISTANCE, Access, PolicyContext, can_stop};
+
+// how close to the box an emergency vehicle is when its intersection starts clearing a path
+pub const PREEMPT_DISTANCE: f64 = 400.0;
+
+// Clears the way for emergency vehicles, whatever policy runs the intersection. Once
+// one comes within PREEMPT_DISTANCE, it and every car queued ahead of it, one after
+// the other also where they move over into a turn pocket, have priority: cars on
+// conflicting routes that can still stop at their line are held there, and the
+// priority cars go as soon as the box is clear of conflicting traffic, or sooner if
+// the policy finds them a way past it. One standing in a queue further out than
+// APPROACH_DISTANCE clears nothing until that queue moves again.
+// Returns None when preemption has nothing to say about the car and the policy decides.
+// A roundabout is left to run as it is, there is no holding the traffic on the ring.
+// Pedestrians already walking across are never driven into, priority or not.
+pub fn preempt(car: &Car, cars: &[Car], ctx: &PolicyContext) -> Option<Access> {
+    let conflicts = ctx.conflicts;
+    if car.ring.is_some() {
+        return None;
+    }
+    let emergency: Vec<&Car> = cars
+        .iter()
+        .filter(|c| {
+            c.is_emergency()
+                && !c.has_left_box()
+                && c.distance_to_box() <= PREEMPT_DISTANCE
+                && (!c.is_waiting || c.distance_to_box() <= APPROACH_DISTANCE)
+        })
+        .collect();
+    if emergency.is_empty() {
+        return None;
+    }
+    // the emergency vehicles and every car in their way, up to the box
+    let mut priority: Vec<&Car> = Vec::new();
+    for e in &emergency {
+        let mut next = Some(*e);
+        while let Some(c) = next {
+            if c.node != e.node || c.has_left_box() || priority.iter().any(|p| p.id == c.id) {
+                break;
+            }
+            priority.push(c);
+            next = c.car_in_front(cars);
+        }
+    }
+    let crosses = |a: &Car, b: &Car| conflicts.between(a, b) && !a.shares_lane(b);
+
+    if priority.iter().any(|p| p.id == car.id) {
+        if policy::walking_across(car, ctx.walkers) {
+            return Some(Access::Stop);
+        }
+        // once on its way through it clears the box, whatever the policy had said
+        if car.occupies_box() || car.distance_to_box() > APPROACH_DISTANCE {
+            return Some(Access::Go(car.cruise_speed()));
+        }
+        let crossing: Vec<&Car> = cars
+            .iter()
+            .filter(|c| crosses(car, c) && !c.has_left_box())
+            .collect();
+        // of two priority cars on crossing routes the one closer to the box goes first
+        let behind = crossing.iter().any(|c| {
+            priority.iter().any(|p| p.id == c.id)
+                && (c.occupies_box()
+                    || (c.distance_to_box(), c.id) < (car.distance_to_box(), car.id))
+        });
+        if behind {
+            return Some(Access::Stop);
+        }
+        // Traffic the policy let through gets out of the way first. The policy knows
+        // about it, so it decides when the car may go, maybe next to it.
+        let committed = crossing.iter().any(|c| c.occupies_box() || !can_stop(c));
+        return (!committed).then(|| Access::Go(car.cruise_speed()));
+    }
+
+    let yields = !car.occupies_box()
+        && !car.has_left_box()
+        && can_stop(car)
+        && car.distance_to_box() <= APPROACH_DISTANCE
+        && priority.iter().any(|p| crosses(car, p));
+    yields.then_some(Access::Stop)
+}
@@ -0,0 +1,40 @@
+use crate::car::Car;
+use crate::policy::{self, APPROACH_DISTANCE, Access, IntersectionPolicy, PolicyContext};
+
+// The original rule, lower ids first, decided at the line. A car that can still stop
+// there waits while a car on a crossing route is in the box or too close to stop at
+// its own line, or has a lower id and comes first in its lane. Once a car is in the box,
+// or past the point it could brake for its line, nothing holds it any more.
+// Pedestrians have right of way at the crosswalks.
+pub struct PriorityPolicy;
+
+impl IntersectionPolicy for PriorityPolicy {
+    fn name(&self) -> &'static str {
+        "priority"
+    }
+
+    fn access(&mut self, car: &Car, cars: &[Car], ctx: &PolicyContext) -> Access {
+        let go = Access::Go(car.cruise_speed());
+        if car.occupies_box() || car.has_left_box() {
+            return go;
+        }
+        if policy::yields_to_walkers(car, cars, ctx) {
+            return Access::Stop;
+        }
+        if !policy::can_stop(car) || car.distance_to_box() > APPROACH_DISTANCE {
+            return go;
+        }
+
+        // a lower id queued behind another car cannot go first, it does not hold anyone
+        let waits = cars.iter().any(|c| {
+            c.id != car.id
+                && !c.has_left_box()
+                && !car.shares_lane(c)
+                && ctx.conflicts.between(car, c)
+                && (c.occupies_box()
+                    || (c.distance_to_box() <= APPROACH_DISTANCE && !policy::can_stop(c))
+                    || (c.id < car.id && policy::at_head(c, cars)))
+        });
+        if waits { Access::Stop } else { go }
+    }
+}
@@ -0,0 +1,420 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::car::{Area, Car};
+use crate::pedestrian::{PEDESTRIAN_SIZE, Pedestrian};
+use crate::policy::{self, APPROACH_DISTANCE, Access, IntersectionPolicy, PolicyContext};
+use crate::simulation::DT;
+
+// radius of the circles used as a footprint when sweeping a path, one covers a car
+const FOOTPRINT_RADIUS: f64 = 25.0;
+// upper bound on a plan, a car that needs longer is refused
+const MAX_PLAN_TICKS: u64 = 600;
+// how long a plan keeps going after the box, so followers can plan behind it
+const TRAIL_TICKS: u64 = 60;
+// how far a car may drift from its plan before its booking is void, in px
+const PLAN_TOLERANCE: f64 = 2.0;
+
+// a tile of the grid at a tick
+type Slot = (i32, i32, u64);
+
+// where a granted car expects to be on each tick from `start` on
+struct Path {
+    start: u64,
+    states: Vec<((f64, f64), f64)>,
+    // the car it planned to follow, its plan is void once the leader's is
+    leader: Option<usize>,
+}
+
+impl Path {
+    fn at(&self, tick: u64) -> Option<((f64, f64), f64)> {
+        let i = tick.checked_sub(self.start)?;
+        self.states.get(i as usize).copied()
+    }
+}
+
+// AIM style manager: the box is cut into square tiles and a car approaching the box
+// simulates its own crossing tick by tick. It may enter only if every tile its
+// footprint touches is free at that tick, in which case those (tile, tick) slots
+// are booked for it. Cars whose paths never share a tile at the same time cross together.
+// A car plans against the plans of the granted cars it may come up behind, so it books
+// the slots it will really reach instead of those of a free road.
+// A pedestrian books the tiles of its way across the crosswalk the same way. While one
+// waits, no car books a way over its crosswalk, so the cars already booked clear it,
+// unless it came after a car that has run out of patience, see `waits_for_cars`.
+// A human driver books nothing. No car books a way across its route while it comes up
+// to its line, and once it is in the box its tiles are booked from where it is.
+pub struct ReservationPolicy {
+    tile_size: f64,
+    // slots kept free before and after each booked tick
+    buffer_ticks: u64,
+    table: HashMap<Slot, usize>,
+    // slots booked by pedestrians, cars keep off them
+    walkers: HashMap<Slot, usize>,
+    granted: HashMap<usize, Path>,
+    // cars that made it into the box, they never ask again
+    crossed: HashSet<usize>,
+}
+
+impl Default for ReservationPolicy {
+    fn default() -> Self {
+        Self::new(20.0, 3)
+    }
+}
+
+impl ReservationPolicy {
+    pub fn new(tile_size: f64, buffer_ticks: u64) -> Self {
+        ReservationPolicy {
+            tile_size,
+            buffer_ticks,
+            table: HashMap::new(),
+            walkers: HashMap::new(),
+            granted: HashMap::new(),
+            crossed: HashSet::new(),
+        }
+    }
+
+    // tiles of the grid laid over the box under a circle at pos
+    fn tiles_under(&self, area: &Area, pos: (f64, f64), radius: f64) -> Vec<(i32, i32)> {
+        let cols = ((area.0.end() - area.0.start()) / self.tile_size).ceil() as i32;
+        let rows = ((area.1.end() - area.1.start()) / self.tile_size).ceil() as i32;
+        let x = pos.0 - area.0.start();
+        let y = pos.1 - area.1.start();
+
+        let first_col = (((x - radius) / self.tile_size).floor() as i32).max(0);
+        let last_col = (((x + radius) / self.tile_size).floor() as i32).min(cols - 1);
+        let first_row = (((y - radius) / self.tile_size).floor() as i32).max(0);
+        let last_row = (((y + radius) / self.tile_size).floor() as i32).min(rows - 1);
+
+        let mut tiles = Vec::new();
+        for col in first_col..=last_col {
+            for row in first_row..=last_row {
+                let left = col as f64 * self.tile_size;
+                let top = row as f64 * self.tile_size;
+                let nearest_x = x.clamp(left, left + self.tile_size);
+                let nearest_y = y.clamp(top, top + self.tile_size);
+                let (dx, dy) = (x - nearest_x, y - nearest_y);
+                if dx * dx + dy * dy < radius * radius {
+                    tiles.push((col, row));
+                }
+            }
+        }
+        tiles
+    }
+
+    // Tiles under the body of the car. A car fits in one footprint circle, a longer
+    // vehicle gets a row of them from its rear to its front.
+    fn tiles_under_body(&self, car: &Car) -> Vec<(i32, i32)> {
+        let reach = car.length() / 2.0 - FOOTPRINT_RADIUS;
+        let steps = (reach / FOOTPRINT_RADIUS).ceil().max(0.0) as i32;
+        let (sin, cos) = car.angle.to_radians().sin_cos();
+        let mut tiles = Vec::new();
+        for k in -steps..=steps {
+            let along = if steps == 0 {
+                0.0
+            } else {
+                reach * k as f64 / steps as f64
+            };
+            let center = (car.position.0 + sin * along, car.position.1 - cos * along);
+            for tile in self.tiles_under(&car.intersection, center, FOOTPRINT_RADIUS) {
+                if !tiles.contains(&tile) {
+                    tiles.push(tile);
+                }
+            }
+        }
+        tiles
+    }
+
+    // (tile, tick) slots the car occupies if it leaves now, with the path it drives,
+    // None if it is stuck behind a car that has not been granted yet
+    fn plan(&self, car: &Car, cars: &[Car], tick: u64) -> Option<(Vec<Slot>, Path)> {
+        let front = car.car_in_front(cars);
+        // in a shared lane the leader may turn off, then the car follows the one ahead of
+        // it on its own route
+        let then = front
+            .filter(|f| car.shares_lane(f) && f.lane != car.lane)
+            .and_then(|f| {
+                let rest: Vec<Car> = cars.iter().filter(|c| c.id != f.id).cloned().collect();
+                let id = car.car_in_front(&rest)?.id;
+                cars.iter().find(|c| c.id == id)
+            });
+        // The granted cars drive along their own plans, and the car picks its leader among
+        // them tick by tick the way it does on the road: a cyclist it can pass, a car
+        // turning off or one merging in is followed exactly as long as it will be, and one
+        // it comes up behind on the way is followed from then on.
+        for other in front.into_iter().chain(then) {
+            self.granted.get(&other.id)?;
+        }
+        let mut ahead: Vec<Car> = cars
+            .iter()
+            .filter(|c| c.id != car.id && self.granted.contains_key(&c.id))
+            .cloned()
+            .collect();
+        let mut ghost = car.clone();
+        let mut entered = false;
+        let mut exited_at = None;
+        let mut slots = Vec::new();
+        let mut states = Vec::new();
+
+        for i in 0..MAX_PLAN_TICKS {
+            let at = tick + i;
+            // cars move on what the others did the tick before, past the end of its plan
+            // one drives on as it did last
+            if i > 0 {
+                for other in ahead.iter_mut() {
+                    if let Some((_, speed)) = self.granted[&other.id].at(at - 1) {
+                        other.speed = speed;
+                    }
+                    other.advance(DT);
+                }
+                ahead.retain(|other| !other.has_finished());
+            }
+            let leader = ghost
+                .car_in_front(&ahead)
+                .map(|front| (ghost.gap_to(front.position, front.length()), front.speed));
+            ghost.drive(ghost.cruise_speed(), leader, DT);
+            states.push((ghost.position, ghost.speed));
+
+            if let Some(exit) = exited_at {
+                if at >= exit + TRAIL_TICKS || ghost.has_finished() {
+                    break;
+                }
+                continue;
+            }
+            if ghost.occupies_box() {
+                entered = true;
+            } else if entered || ghost.has_finished() {
+                exited_at = Some(at);
+                continue;
+            }
+            for (col, row) in self.tiles_under_body(&ghost) {
+                slots.push((col, row, at));
+            }
+        }
+
+        exited_at?;
+        let path = Path {
+            start: tick,
+            states,
+            leader: front.map(|f| f.id),
+        };
+        Some((slots, path))
+    }
+
+    // (tile, tick) slots of the pedestrian's way across if it sets off now
+    fn walker_slots(&self, walker: &Pedestrian, tick: u64) -> Vec<Slot> {
+        let area = &walker.crosswalk.intersection;
+        let ticks = (walker.time_to_cross() / DT).ceil() as u64;
+        let mut slots = Vec::new();
+        for i in 0..=ticks {
+            let pos = walker.ahead(i as f64 * DT);
+            for (col, row) in self.tiles_under(area, pos, PEDESTRIAN_SIZE) {
+                slots.push((col, row, tick + i));
+            }
+        }
+        slots
+    }
+
+    fn is_free(&self, slots: &[Slot], table: &HashMap<Slot, usize>) -> bool {
+        slots.iter().all(|&(col, row, at)| {
+            let from = at.saturating_sub(self.buffer_ticks);
+            (from..=at + self.buffer_ticks).all(|t| !table.contains_key(&(col, row, t)))
+        })
+    }
+
+    // drops the booking of the car and of every car that planned behind it
+    fn release(&mut self, id: usize) {
+        self.table.retain(|_, owner| *owner != id);
+        self.granted.remove(&id);
+
+        let followers: Vec<usize> = self
+            .granted
+            .iter()
+            .filter(|(_, path)| path.leader == Some(id))
+            .map(|(&follower, _)| follower)
+            .collect();
+        for follower in followers {
+            if !self.crossed.contains(&follower) {
+                self.release(follower);
+            }
+        }
+    }
+}
+
+impl IntersectionPolicy for ReservationPolicy {
+    fn name(&self) -> &'static str {
+        "reservation"
+    }
+
+    fn update(&mut self, cars: &[Car], ctx: &PolicyContext) {
+        let tick = (ctx.time / DT).round() as u64;
+        let oldest = tick.saturating_sub(self.buffer_ticks);
+        self.table.retain(|&(_, _, at), _| at >= oldest);
+        self.walkers.retain(|&(_, _, at), _| at >= oldest);
+
+        for c in cars {
+            if c.in_intersection() {
+                self.crossed.insert(c.id);
+            }
+        }
+
+        // a pedestrian held up on its way across books the rest of it again from where it is
+        for walker in ctx.walkers.iter().filter(|w| w.walking) {
+            self.walkers.retain(|_, owner| *owner != walker.id);
+            for slot in self.walker_slots(walker, tick) {
+                self.walkers.insert(slot, walker.id);
+            }
+        }
+
+        // a car that fell behind its plan before the box would miss its slots, it has to ask
+        // again if it can still stop at its line
+        let late: Vec<usize> = cars
+            .iter()
+            .filter(|c| !self.crossed.contains(&c.id) && policy::can_stop(c))
+            .filter(|c| {
+                self.granted
+                    .get(&c.id)
+                    .and_then(|path| path.at(tick - 1))
+                    .is_some_and(|(pos, _)| c.distance_to(pos) > PLAN_TOLERANCE)
+            })
+            .map(|c| c.id)
+            .collect();
+        for id in late {
+            self.release(id);
+        }
+
+        // A car held up in the box, by a queue running back from the next intersection,
+        // is still on tiles it booked to have left. It books its way out again from where
+        // it is, and cars that booked those slots in the meantime have to ask again, all
+        // but the one it now plans to follow, it only trails that one's way. So does a car
+        // that fell behind too close to the box to stop, it would stand on the crosswalk
+        // otherwise. A human driver in the box is booked the same way, it never booked
+        // anything itself. The cars that planned behind its old path and can still stop
+        // ask again too, before they drift off their plans too close to the box.
+        let held: Vec<&Car> = cars
+            .iter()
+            .filter(|c| {
+                c.occupies_box() || (self.granted.contains_key(&c.id) && !policy::can_stop(c))
+            })
+            .filter(|c| {
+                self.granted
+                    .get(&c.id)
+                    .and_then(|path| path.at(tick - 1))
+                    .is_none_or(|(pos, _)| c.distance_to(pos) > PLAN_TOLERANCE)
+            })
+            .collect();
+        for car in held {
+            self.table.retain(|_, owner| *owner != car.id);
+            let (slots, path) = self.plan(car, cars, tick).unwrap_or_else(|| {
+                // stuck for longer than a plan reaches, it keeps the tiles it stands on
+                let slots = self
+                    .tiles_under_body(car)
+                    .into_iter()
+                    .flat_map(|(col, row)| (tick..tick + TRAIL_TICKS).map(move |t| (col, row, t)))
+                    .collect();
+                let path = Path {
+                    start: tick,
+                    states: vec![(car.position, car.speed); TRAIL_TICKS as usize],
+                    leader: None,
+                };
+                (slots, path)
+            });
+            let bumped: HashSet<usize> = slots
+                .iter()
+                .flat_map(|&(col, row, at)| {
+                    let from = at.saturating_sub(self.buffer_ticks);
+                    (from..=at + self.buffer_ticks).map(move |t| (col, row, t))
+                })
+                .filter_map(|slot| self.table.get(&slot).copied())
+                .filter(|&owner| !self.crossed.contains(&owner) && path.leader != Some(owner))
+                .collect();
+            for id in bumped {
+                self.release(id);
+            }
+            let followers: Vec<usize> = cars
+                .iter()
+                .filter(|c| !self.crossed.contains(&c.id) && policy::can_stop(c))
+                .filter(|c| {
+                    self.granted
+                        .get(&c.id)
+                        .is_some_and(|path| path.leader == Some(car.id))
+                })
+                .map(|c| c.id)
+                .collect();
+            for id in followers {
+                self.release(id);
+            }
+            for slot in slots {
+                self.table.insert(slot, car.id);
+            }
+            self.granted.insert(car.id, path);
+        }
+
+        let alive: HashSet<usize> = cars.iter().map(|c| c.id).collect();
+        self.granted.retain(|id, _| alive.contains(id));
+        self.crossed.retain(|id| alive.contains(id));
+    }
+
+    fn access(&mut self, car: &Car, cars: &[Car], ctx: &PolicyContext) -> Access {
+        let go = Access::Go(car.cruise_speed());
+        if self.granted.contains_key(&car.id)
+            || self.crossed.contains(&car.id)
+            || car.in_intersection()
+            || car.distance_to_box() > APPROACH_DISTANCE
+        {
+            return go;
+        }
+
+        // cars from other lanes merging onto the same exit lane take turns, so a merging
+        // car is always planned against as a leader that has already left the box
+        let merging = cars.iter().any(|c| {
+            self.granted.contains_key(&c.id)
+                && !c.shares_lane(car)
+                && c.exits_with(car)
+                && !c.has_left_box()
+        });
+        let walker_waits = ctx.walkers.iter().any(|w| {
+            !w.walking
+                && w.crosswalk.in_path(car)
+                && policy::stops_short(car, &w.crosswalk)
+                && !policy::waits_for_cars(w, cars, ctx)
+        });
+        if merging || walker_waits || policy::human_waits(car, cars, ctx) {
+            return Access::Stop;
+        }
+
+        let tick = (ctx.time / DT).round() as u64;
+        match self.plan(car, cars, tick) {
+            Some((slots, path))
+                if self.is_free(&slots, &self.table) && self.is_free(&slots, &self.walkers) =>
+            {
+                for slot in slots {
+                    self.table.insert(slot, car.id);
+                }
+                self.granted.insert(car.id, path);
+                go
+            }
+            _ => Access::Stop,
+        }
+    }
+
+    fn hold(&mut self, car: &Car) {
+        if !self.crossed.contains(&car.id) {
+            self.release(car.id);
+        }
+    }
+
+    fn walk(&mut self, walker: &Pedestrian, cars: &[Car], ctx: &PolicyContext) -> bool {
+        let tick = (ctx.time / DT).round() as u64;
+        let slots = self.walker_slots(walker, tick);
+        if !self.is_free(&slots, &self.table)
+            || !policy::crosswalk_clear(walker, cars)
+            || policy::waits_for_cars(walker, cars, ctx)
+        {
+            return false;
+        }
+        for slot in slots {
+            self.walkers.insert(slot, walker.id);
+        }
+        true
+    }
+}
@@ -0,0 +1,133 @@
+use crate::car::{Car, Direction};
+use crate::layout::Layout;
+use crate::pedestrian::Pedestrian;
+use crate::policy::{self, Access, Crossings, IntersectionPolicy, PolicyContext};
+
+// Emulates a fixed time signal with split phasing: each approach of the layout gets its
+// own green in turn, clockwise from north, followed by an all red clearance so the box empties before the next one.
+// The clearance runs on until the last car let in is out, a slow one can take longer.
+// With pedestrians waiting at the end of a cycle, a walk phase follows in which every
+// crosswalk gets the green and no approach does; the next cycle starts after it.
+pub struct TrafficLightPolicy {
+    phases: Vec<Direction>,
+    green: f64,
+    clearance: f64,
+    walk: f64,
+    // when the running cycle began, and when the walk phase after it ends
+    cycle_start: f64,
+    walk_until: Option<f64>,
+    // time of the last update, the clock stands still while the box clears
+    last_update: f64,
+    crossings: Crossings,
+}
+
+impl TrafficLightPolicy {
+    pub fn new(layout: &Layout, green: f64, clearance: f64, walk: f64) -> Self {
+        // an arm cars only leave on never needs a green
+        let mut arms: Vec<_> = layout
+            .arms
+            .iter()
+            .filter(|arm| !arm.lanes.is_empty())
+            .collect();
+        arms.sort_by(|a, b| {
+            let bearing = |arm| layout.bearing_of(arm).rem_euclid(360.0);
+            bearing(a).total_cmp(&bearing(b))
+        });
+        TrafficLightPolicy {
+            phases: arms.iter().map(|arm| arm.from).collect(),
+            green,
+            clearance,
+            walk,
+            cycle_start: 0.0,
+            walk_until: None,
+            last_update: 0.0,
+            crossings: Crossings::default(),
+        }
+    }
+
+    fn cycle(&self) -> f64 {
+        (self.green + self.clearance) * self.phases.len() as f64
+    }
+
+    // approach that has green at this time, None during the clearance and the walk phase
+    pub fn green_for(&self, time: f64) -> Option<Direction> {
+        if self.walk_until.is_some() {
+            return None;
+        }
+        let phase_length = self.green + self.clearance;
+        let in_cycle = (time - self.cycle_start).rem_euclid(self.cycle());
+        let phase = (in_cycle / phase_length) as usize;
+        if in_cycle - phase as f64 * phase_length < self.green {
+            Some(self.phases[phase])
+        } else {
+            None
+        }
+    }
+}
+
+impl IntersectionPolicy for TrafficLightPolicy {
+    fn name(&self) -> &'static str {
+        "lights"
+    }
+
+    fn update(&mut self, cars: &[Car], ctx: &PolicyContext) {
+        self.crossings.update(cars, ctx.walkers);
+        // cars let through behind one held at its line would keep the box from ever
+        // clearing, and the next green from coming
+        self.crossings.withdraw_blocked(cars);
+        let dt = ctx.time - self.last_update;
+        self.last_update = ctx.time;
+        if self.walk_until.is_none()
+            && self.green_for(ctx.time).is_none()
+            && cars.iter().any(|c| self.crossings.is_active(c.id))
+        {
+            self.cycle_start += dt;
+        }
+        match self.walk_until {
+            Some(end) if ctx.time >= end => {
+                self.walk_until = None;
+                self.cycle_start = end;
+            }
+            Some(_) => {}
+            None if ctx.time - self.cycle_start >= self.cycle() => {
+                self.cycle_start += self.cycle();
+                if ctx.walkers.iter().any(|w| !w.walking) {
+                    self.walk_until = Some(self.cycle_start + self.walk);
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn access(&mut self, car: &Car, cars: &[Car], ctx: &PolicyContext) -> Access {
+        if !self.crossings.is_waiting(car.id) {
+            return Access::Go(car.cruise_speed());
+        }
+        if self.green_for(ctx.time) != Some(car.direction)
+            || policy::walking_across(car, ctx.walkers)
+            || self.crossings.is_blocked(car, cars)
+        {
+            return Access::Stop;
+        }
+        self.crossings.commit(car.id);
+        Access::Go(car.cruise_speed())
+    }
+
+    fn hold(&mut self, car: &Car) {
+        self.crossings.withdraw(car.id);
+    }
+
+    fn red_for(&self, car: &Car, ctx: &PolicyContext) -> bool {
+        self.green_for(ctx.time) != Some(car.direction)
+    }
+
+    // across in the walk phase, if there is time left to get to the other side
+    fn walk(&mut self, walker: &Pedestrian, cars: &[Car], ctx: &PolicyContext) -> bool {
+        self.walk_until
+            .is_some_and(|end| ctx.time + walker.time_to_cross() <= end)
+            && !cars
+                .iter()
+                .any(|c| walker.crosswalk.in_path(c) && self.crossings.is_active(c.id))
+            && policy::crosswalk_clear(walker, cars)
+    }
+}
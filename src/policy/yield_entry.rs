@@ -0,0 +1,132 @@
+use std::f64::consts::TAU;
+
+use crate::car::{Car, MAX_ACCEL, RING_SPEED, STOP_LINE_OFFSET, VEHICLE_LENGTH, VEHICLE_WIDTH};
+use crate::layout::Point;
+use crate::policy::{self, APPROACH_DISTANCE, Access, IntersectionPolicy, PolicyContext};
+
+// seconds of free ring a car needs before it pulls out in front of circulating traffic
+const CRITICAL_GAP: f64 = 1.5;
+
+// Give way at the entry. On a roundabout a waiting car joins the ring once no car on
+// it will reach the point where it joins within the critical gap and nothing stands
+// on that point. Without a ring it is an all-way give way: wait while any car the
+// route conflicts with is in the box, too close to stop, or first in its lane and nearer
+// its line. Either way cars give way to pedestrians at the crosswalks.
+pub struct YieldPolicy;
+
+impl IntersectionPolicy for YieldPolicy {
+    fn name(&self) -> &'static str {
+        "yield"
+    }
+
+    fn access(&mut self, car: &Car, cars: &[Car], ctx: &PolicyContext) -> Access {
+        let go = Access::Go(car.cruise_speed());
+        // a long vehicle driving out still has its tail in the box, it goes on too
+        if car.occupies_box() || car.has_left_box() || car.distance_to_box() > APPROACH_DISTANCE {
+            return go;
+        }
+        if policy::yields_to_walkers(car, cars, ctx) {
+            return Access::Stop;
+        }
+
+        let give_way = match car.ring {
+            Some(_) => gives_way_on_ring(car, cars.iter()),
+            // too close to stop at its line it is as good as in the box
+            None if !policy::can_stop(car) => false,
+            // Of two cars first in their lanes the one nearer its line goes first. A car
+            // queued behind another cannot go yet and holds nobody up.
+            None => cars.iter().any(|c| {
+                c.id != car.id
+                    && !c.has_left_box()
+                    && !car.shares_lane(c)
+                    && ctx.conflicts.between(car, c)
+                    && (c.occupies_box()
+                        || (c.distance_to_box() <= APPROACH_DISTANCE && !policy::can_stop(c))
+                        || (policy::at_head(c, cars)
+                            && (to_line(c), c.id) < (to_line(car), car.id)))
+            }),
+        };
+        if give_way { Access::Stop } else { go }
+    }
+}
+
+// The car waits at its entry for one of the others on the ring to go by. Past its line
+// it could only stop with its nose in the ring, it goes on.
+pub fn gives_way_on_ring<'a>(car: &Car, mut others: impl Iterator<Item = &'a Car>) -> bool {
+    let Some(ring) = car.ring.filter(|_| !pulls_out(car)) else {
+        return false;
+    };
+    entry_point(car, ring).is_some_and(|entry| {
+        let gap = critical_gap(car);
+        others.any(|c| c.id != car.id && reaches(c, entry, ring, gap, car.overhang()))
+    })
+}
+
+// how far the car still has to its stop line, past it counts as at it
+fn to_line(car: &Car) -> f64 {
+    car.distance_to_stop_line()
+        .map_or(0.0, |line| line.max(0.0))
+}
+
+// A longer or slower vehicle takes longer to pull out from its stop line onto the
+// ring, and waits for a gap longer by the difference to a car. One still rolling up
+// to its line needs the gap to last until it gets there too, a heavy one could not
+// stop any more if a car turned up on the ring in the meantime.
+fn critical_gap(car: &Car) -> f64 {
+    let pull_out = |distance: f64, accel: f64| (2.0 * distance / accel).sqrt();
+    // a car waiting at its line stands the minimum gap short of it
+    let to_line = car.distance_to_stop_line().map_or(0.0, |line| {
+        (line - car.idm.min_gap).max(0.0) / car.speed.max(1.0)
+    });
+    CRITICAL_GAP
+        + to_line
+        + pull_out(
+            STOP_LINE_OFFSET + car.overhang() + car.length(),
+            car.max_accel,
+        )
+        - pull_out(STOP_LINE_OFFSET + VEHICLE_LENGTH, MAX_ACCEL)
+}
+
+// rolling over its stop line onto the ring
+fn pulls_out(car: &Car) -> bool {
+    car.speed > 0.0 && car.distance_to_stop_line().is_some_and(|line| line < 0.0)
+}
+
+// where the route of the car first reaches the ring lane
+fn entry_point(car: &Car, ring: (Point, f64)) -> Option<Point> {
+    car.waypoints
+        .iter()
+        .map(|w| (w.x, w.y))
+        .find(|&p| on_ring(p, ring))
+}
+
+fn on_ring(p: Point, ((cx, cy), radius): (Point, f64)) -> bool {
+    ((p.0 - cx).hypot(p.1 - cy) - radius).abs() < VEHICLE_WIDTH
+}
+
+// The other car stands on the entry, or drives past it on the ring within the gap.
+// Turning off just before the entry counts as driving past for a long vehicle, its
+// tail swings across the entry as it leaves, as does the tail of a long one pulling in.
+fn reaches(other: &Car, entry: Point, ring: (Point, f64), gap: f64, swing: f64) -> bool {
+    if other.distance_to(entry) < other.length() {
+        return true;
+    }
+    // a car still pulling in from its own entry counts as on the ring already
+    if !other.in_intersection() && !pulls_out(other) {
+        return false;
+    }
+    let ((cx, cy), radius) = ring;
+    let angle = |p: Point| (cy - p.1).atan2(p.0 - cx);
+    let at = angle(other.position);
+    let to_entry = (angle(entry) - at).rem_euclid(TAU);
+    // the last point of its route on the ring is where it leaves
+    let leaves = other
+        .waypoints
+        .iter()
+        .rev()
+        .map(|w| (w.x, w.y))
+        .find(|&p| on_ring(p, ring))
+        .map_or(0.0, |p| (angle(p) - at).rem_euclid(TAU));
+    let sweep = (other.length() / 2.0 + swing) / radius;
+    leaves + sweep > to_entry && to_entry * radius < other.speed.max(RING_SPEED) * gap
+}
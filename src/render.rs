@@ -0,0 +1,447 @@
+use sdl2::EventPump;
+use sdl2::VideoSubsystem;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use std::f64::consts::TAU;
+
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::surface::Surface;
+use sdl2::video::Window;
+
+use smart_road::air::{FLIGHT_LEVELS, Plane};
+use smart_road::car::{Car, Direction};
+use smart_road::layout::{Arm, LANE_CHANGE_LENGTH, Layout, Point};
+use smart_road::pedestrian::{Crosswalk, PEDESTRIAN_SIZE, Pedestrian};
+use smart_road::simulation::{Simulation, Stats};
+
+// markings on the roads
+const DASH_LENGTH: f64 = 30.0;
+const DASH_STEP: f64 = 60.0;
+const LINE_WIDTH: f64 = 2.0;
+const STOP_LINE_WIDTH: f64 = 5.0;
+// white bars of a zebra crossing and the gaps between them
+const ZEBRA_STRIPE: f64 = 8.0;
+const ZEBRA_STEP: f64 = 16.0;
+// pieces the ring of a roundabout is drawn with
+const RING_PIECES: usize = 90;
+// size of a plane sprite at the lowest flight level, it grows as the plane climbs
+const PLANE_SIZE: (f64, f64) = (120.0, 80.0);
+// px a shadow falls off to the bottom right per foot of altitude, the sun stands top left
+const SHADOW_OFFSET: f64 = 0.012;
+// dot on the roof of a car with a human driver
+const HUMAN_MARK: f64 = 8.0;
+
+pub struct Sprites<'a> {
+    pub cars: Vec<Texture<'a>>,
+    pub planes: Vec<Texture<'a>>,
+    // the plane sprites again, darkened and see-through
+    pub shadows: Vec<Texture<'a>>,
+    pub backgrounds: Vec<Texture<'a>>,
+    pub paint: Paint<'a>,
+}
+
+// plain colours the roads are drawn with, as textures so they can be turned with the arms
+pub struct Paint<'a> {
+    pub asphalt: Texture<'a>,
+    pub line: Texture<'a>,
+    pub island: Texture<'a>,
+    pub stop_line: Texture<'a>,
+    pub pedestrian: Texture<'a>,
+    pub human: Texture<'a>,
+}
+
+impl<'a> Paint<'a> {
+    pub fn new<T>(creator: &'a TextureCreator<T>) -> Self {
+        let solid = |color: Color| {
+            let mut surface = Surface::new(1, 1, PixelFormatEnum::RGB888).unwrap();
+            surface.fill_rect(None, color).unwrap();
+            creator.create_texture_from_surface(&surface).unwrap()
+        };
+        Paint {
+            asphalt: solid(Color::RGB(23, 23, 23)),
+            line: solid(Color::WHITE),
+            island: solid(Color::RGB(70, 70, 70)),
+            stop_line: solid(Color::YELLOW),
+            pedestrian: solid(Color::RGB(255, 140, 0)),
+            human: solid(Color::RGB(0, 200, 255)),
+        }
+    }
+}
+
+pub fn draw_world(canvas: &mut Canvas<Window>, sprites: &Sprites, sim: &Simulation) {
+    // grass first, roads may run into the next intersection's quarters
+    for node in &sim.network.nodes {
+        draw_grass(canvas, &node.layout, &sprites.backgrounds);
+    }
+    for node in &sim.network.nodes {
+        draw_roads(canvas, &node.layout, &sprites.paint);
+    }
+    for crosswalk in &sim.crosswalks {
+        draw_crosswalk(canvas, crosswalk, &sprites.paint);
+    }
+    for car in &sim.cars {
+        draw_car(canvas, sprites, car);
+    }
+    for walker in &sim.pedestrians {
+        draw_pedestrian(canvas, walker, &sprites.paint);
+    }
+    // all shadows before any plane, a low plane passes over the shadow of a high one
+    for plane in &sim.air.planes {
+        let (x, y) = plane.position;
+        let offset = plane.altitude * SHADOW_OFFSET;
+        draw_plane(
+            canvas,
+            &sprites.shadows,
+            plane,
+            (x + offset, y + offset),
+            1.0,
+        );
+    }
+    let mut planes: Vec<&Plane> = sim.air.planes.iter().collect();
+    planes.sort_by(|a, b| a.altitude.total_cmp(&b.altitude));
+    for plane in planes {
+        let scale = plane.altitude / FLIGHT_LEVELS[0];
+        draw_plane(canvas, &sprites.planes, plane, plane.position, scale);
+    }
+}
+
+fn draw_plane(
+    canvas: &mut Canvas<Window>,
+    pool: &[Texture],
+    plane: &Plane,
+    (x, y): Point,
+    scale: f64,
+) {
+    let (w, h) = (PLANE_SIZE.0 * scale.sqrt(), PLANE_SIZE.1 * scale.sqrt());
+    let rect = Rect::new(
+        (x - w / 2.0).round() as i32,
+        (y - h / 2.0).round() as i32,
+        w.round() as u32,
+        h.round() as u32,
+    );
+    canvas
+        .copy_ex(
+            &pool[plane.sprite],
+            None,
+            Some(rect),
+            plane.heading,
+            None,
+            false,
+            false,
+        )
+        .unwrap();
+}
+
+// The grass pictures in the four quarters around the centre, out to where the roads
+// meet the next intersection or to the edge of the world.
+fn draw_grass(canvas: &mut Canvas<Window>, layout: &Layout, backgrounds: &[Texture]) {
+    let (cx, cy) = layout.center;
+    let reach = |from, edge: f64| {
+        layout
+            .arm(from)
+            .filter(|arm| arm.bearing.is_none())
+            .map_or(edge, |arm| layout.length_of(arm))
+    };
+    let left = cx - reach(Direction::West, cx);
+    let right = cx + reach(Direction::East, layout.width - cx);
+    let top = cy - reach(Direction::North, cy);
+    let bottom = cy + reach(Direction::South, layout.height - cy);
+    // top left, bottom left, top right, bottom right
+    let quarters = [
+        ((left, top), (cx, cy)),
+        ((left, cy), (cx, bottom)),
+        ((cx, top), (right, cy)),
+        ((cx, cy), (right, bottom)),
+    ];
+    for (texture, (from, to)) in backgrounds.iter().zip(quarters) {
+        canvas
+            .copy(texture, None, Some(rect_between(from, to)))
+            .unwrap();
+    }
+}
+
+// Everything drawn here comes from the layout: asphalt for each arm and the ring of a
+// roundabout, dashed lines between lanes going the same way, a solid centre line and
+// yellow stop lines at the box.
+fn draw_roads(canvas: &mut Canvas<Window>, layout: &Layout, paint: &Paint) {
+    let (cx, cy) = layout.center;
+    for arm in &layout.arms {
+        let (inbound, outbound, width) = widths(layout, arm);
+        // at a junction the arms overlap in the middle, at a roundabout they end on the ring
+        let start = match layout.roundabout {
+            Some(ring) => ring.radius,
+            None => -inbound.max(outbound) * width,
+        };
+        fill_along(
+            canvas,
+            &paint.asphalt,
+            layout,
+            arm,
+            (-outbound * width, inbound * width),
+            (start, layout.length_of(arm)),
+        );
+    }
+    if let Some(ring) = layout.roundabout {
+        for k in 0..RING_PIECES {
+            let a = TAU * k as f64 / RING_PIECES as f64;
+            let length = TAU * (ring.radius + layout.lane_width / 2.0) / RING_PIECES as f64;
+            fill_strip(
+                canvas,
+                &paint.asphalt,
+                (cx + ring.radius * a.cos(), cy - ring.radius * a.sin()),
+                (length + 2.0, layout.lane_width),
+                (-a.cos()).atan2(-a.sin()).to_degrees(),
+            );
+        }
+    }
+
+    for arm in &layout.arms {
+        let (inbound, outbound, width) = widths(layout, arm);
+        let reach = layout.box_reach(arm.from);
+        let length = layout.length_of(arm);
+        let mut fill = |paint, across, along| fill_along(canvas, paint, layout, arm, across, along);
+
+        let dividers = (1..inbound as usize)
+            .map(|k| k as f64 * width)
+            .chain((1..outbound as usize).map(|k| -(k as f64) * width));
+        let half = LINE_WIDTH / 2.0;
+        for offset in dividers {
+            let mut along = reach;
+            while along < length {
+                fill(
+                    &paint.line,
+                    (offset - half, offset + half),
+                    (along, (along + DASH_LENGTH).min(length)),
+                );
+                along += DASH_STEP;
+            }
+        }
+        fill(&paint.line, (-half, half), (reach, length));
+
+        // no lane before a turn pocket opens, the island tapers off over the lane change
+        for (i, lane) in arm.lanes.iter().enumerate() {
+            if let Some(pocket) = lane.pocket {
+                let opens = reach + pocket + LANE_CHANGE_LENGTH / 2.0;
+                fill(
+                    &paint.island,
+                    (i as f64 * width, (i + 1) as f64 * width),
+                    (opens, length),
+                );
+            }
+        }
+
+        if inbound > 0.0 {
+            fill(
+                &paint.stop_line,
+                (0.0, inbound * width),
+                (reach, reach + STOP_LINE_WIDTH),
+            );
+        }
+    }
+}
+
+// inbound and outbound lane counts of the arm and its lane width
+fn widths(layout: &Layout, arm: &Arm) -> (f64, f64, f64) {
+    (
+        arm.lanes.len() as f64,
+        arm.exit_lanes as f64,
+        layout.lane_width_of(arm),
+    )
+}
+
+// paints the part of the arm between two offsets across it and two distances along it
+fn fill_along(
+    canvas: &mut Canvas<Window>,
+    paint: &Texture,
+    layout: &Layout,
+    arm: &Arm,
+    across: (f64, f64),
+    along: (f64, f64),
+) {
+    let a = layout.point_on(arm.from, across.0, along.0);
+    let b = layout.point_on(arm.from, across.1, along.1);
+    let (dx, dy) = layout.inbound(arm.from);
+    fill_strip(
+        canvas,
+        paint,
+        ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0),
+        ((along.1 - along.0).abs(), (across.1 - across.0).abs()),
+        (-dy).atan2(-dx).to_degrees(),
+    );
+}
+
+// a length x width rectangle around centre, turned clockwise by angle degrees
+fn fill_strip(
+    canvas: &mut Canvas<Window>,
+    paint: &Texture,
+    centre: Point,
+    (length, width): (f64, f64),
+    angle: f64,
+) {
+    let rect = Rect::new(
+        (centre.0 - length / 2.0).round() as i32,
+        (centre.1 - width / 2.0).round() as i32,
+        length.round().max(1.0) as u32,
+        width.round().max(1.0) as u32,
+    );
+    canvas
+        .copy_ex(paint, None, Some(rect), angle, None, false, false)
+        .unwrap();
+}
+
+// bars along the road from kerb to kerb, the crosswalk may run skew to the road
+fn draw_crosswalk(canvas: &mut Canvas<Window>, crosswalk: &Crosswalk, paint: &Paint) {
+    let (a, b) = crosswalk.ends;
+    let length = crosswalk.length();
+    let along = ((b.0 - a.0) / length, (b.1 - a.1) / length);
+    let angle = along.1.atan2(along.0).to_degrees() + 90.0;
+    let mut at = ZEBRA_STEP / 2.0;
+    while at < length {
+        fill_strip(
+            canvas,
+            &paint.line,
+            (a.0 + along.0 * at, a.1 + along.1 * at),
+            (crosswalk.width, ZEBRA_STRIPE),
+            angle,
+        );
+        at += ZEBRA_STEP;
+    }
+}
+
+fn draw_pedestrian(canvas: &mut Canvas<Window>, walker: &Pedestrian, paint: &Paint) {
+    fill_strip(
+        canvas,
+        &paint.pedestrian,
+        walker.position,
+        (PEDESTRIAN_SIZE, PEDESTRIAN_SIZE),
+        0.0,
+    );
+}
+
+fn rect_between(a: Point, b: Point) -> Rect {
+    Rect::new(
+        a.0.min(b.0).round() as i32,
+        a.1.min(b.1).round() as i32,
+        (a.0 - b.0).abs().round().max(1.0) as u32,
+        (a.1 - b.1).abs().round().max(1.0) as u32,
+    )
+}
+
+fn draw_car(canvas: &mut Canvas<Window>, sprites: &Sprites, car: &Car) {
+    let (w, h) = car.vehicle.spec().sprite_size();
+
+    let rect = Rect::new(
+        (car.position.0 as i32) - (w as i32) / 2,
+        (car.position.1 as i32) - (h as i32) / 2,
+        w,
+        h,
+    );
+
+    canvas
+        .copy_ex(
+            &sprites.cars[car.sprite],
+            None,
+            Some(rect),
+            car.angle,
+            None,
+            false,
+            false,
+        )
+        .unwrap();
+    if car.human {
+        fill_strip(
+            canvas,
+            &sprites.paint.human,
+            car.position,
+            (HUMAN_MARK, HUMAN_MARK),
+            car.angle,
+        );
+    }
+}
+
+pub fn show_stats(video_subsystem: &VideoSubsystem, event_pump: &mut EventPump, stats: &Stats) {
+    let ttf_context = sdl2::ttf::init().expect("Failed to init TTF");
+    let font = ttf_context.load_font("assets/Roboto.ttf", 32).unwrap();
+
+    let stats_window = video_subsystem
+        .window("Simulation Stats", 800, 600)
+        .position_centered()
+        .build()
+        .unwrap();
+
+    let mut stats_canvas = stats_window.into_canvas().build().unwrap();
+    let texture_creator = stats_canvas.texture_creator();
+
+    let lines = [
+        format!("Total Cars Spawned: {}", stats.spawned),
+        format!("Max Time: {:.2}s", stats.max_duration()),
+        format!("Min Time: {:.2}s", stats.min_duration().unwrap_or_default()),
+        format!(
+            "Speed: mean {:.0} px/s, max {:.0} px/s",
+            stats.mean_speed().unwrap_or_default(),
+            stats.max_speed()
+        ),
+        format!(
+            "Delay: median {:.2}s, 95th percentile {:.2}s",
+            stats.delay_percentile(50.0).unwrap_or_default(),
+            stats.delay_percentile(95.0).unwrap_or_default()
+        ),
+        format!("Longest Entry Queue: {} cars", stats.max_queue),
+        format!(
+            "Emergency Delay: {:.2}s, Others: {:.2}s",
+            stats.mean_delay(true).unwrap_or_default(),
+            stats.mean_delay(false).unwrap_or_default()
+        ),
+        format!(
+            "Cyclist Delay: {:.2}s, Motor Traffic: {:.2}s",
+            stats.mean_cyclist_delay(true).unwrap_or_default(),
+            stats.mean_cyclist_delay(false).unwrap_or_default()
+        ),
+        format!(
+            "Pedestrians: {} crossed, mean wait {:.2}s",
+            stats.crossed,
+            stats.mean_pedestrian_wait().unwrap_or_default()
+        ),
+        format!("Close Calls: {}", stats.close_calls),
+        format!("Collisions: {}", stats.collisions.len()),
+    ];
+    let text_textures: Vec<Texture> = lines
+        .iter()
+        .map(|line| {
+            let surface = font.render(line).blended(Color::WHITE).unwrap();
+            texture_creator
+                .create_texture_from_surface(&surface)
+                .unwrap()
+        })
+        .collect();
+
+    'stats_loop: loop {
+        stats_canvas.set_draw_color(Color::RGB(0, 0, 0));
+        stats_canvas.clear();
+        for (i, texture) in text_textures.iter().enumerate() {
+            stats_canvas
+                .copy(
+                    texture,
+                    None,
+                    Some(Rect::new(50, 60 + 50 * i as i32, 400, 40)),
+                )
+                .unwrap();
+        }
+        stats_canvas.present();
+
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => {
+                    break 'stats_loop;
+                }
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::car::{Direction, Movement};
+use crate::demand::{Demand, Platoon};
+use crate::layout::{LAYOUT_NAMES, Layout};
+use crate::network::{Approach, Network};
+use crate::spawn_cars::{Request, Turning, TurningRatios};
+use crate::vehicle::VehicleType;
+
+// A scripted run: settings that stand in for command line options, a list of cars to
+// spawn at given times, so a case like four simultaneous left turns can be replayed
+// exactly, and the traffic demand on each approach. Settings left out keep their
+// command line value.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub seed: Option<u64>,
+    pub policy: Option<String>,
+    // built in layout name or a layout file, relative to the scenario file
+    pub layout: Option<String>,
+    // network file, relative to the scenario file
+    pub network: Option<String>,
+    pub seconds: Option<f64>,
+    pub headway: Option<f64>,
+    pub min_gap: Option<f64>,
+    pub right_radius: Option<f64>,
+    pub left_radius: Option<f64>,
+    // movements of random cars and of demand without turning shares of its own
+    pub turning: Option<TurningRatios>,
+    // intersections clear a path for emergency vehicles, on unless set to false
+    pub preemption: Option<bool>,
+    // planes generated per minute over the road
+    pub planes: Option<f64>,
+    // pedestrians coming to the crosswalks per minute
+    pub pedestrians: Option<f64>,
+    // percent of the random traffic that are cyclists
+    pub cyclists: Option<f64>,
+    // percent of the random traffic driven by humans
+    pub humans: Option<f64>,
+    // random traffic on top of the spawns below
+    #[serde(default)]
+    pub random_traffic: bool,
+    #[serde(default)]
+    spawns: Vec<SpawnFile>,
+    #[serde(default)]
+    demand: Vec<DemandFile>,
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpawnFile {
+    // seconds after the start
+    time: f64,
+    from: Direction,
+    // intersection name, the first one with an open arm on that side if left out
+    at: Option<String>,
+    movement: Option<Movement>,
+    lane: Option<usize>,
+    // exit of a network: [intersection, side]
+    to: Option<(String, Direction)>,
+    vehicle: Option<VehicleType>,
+    speed: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DemandFile {
+    from: Direction,
+    at: Option<String>,
+    // vehicles per hour all run long
+    rate: Option<f64>,
+    // or [seconds, vehicles per hour] points to ramp between
+    profile: Option<Vec<(f64, f64)>>,
+    // Poisson arrivals of single cars if left out
+    platoon: Option<Platoon>,
+    turning: Option<Turning>,
+}
+
+// one car of the schedule, ready for the simulation
+#[derive(Debug, Clone, Copy)]
+pub struct Spawn {
+    pub time: f64,
+    pub origin: Approach,
+    pub request: Request,
+}
+
+impl Scenario {
+    pub fn load(path: &str) -> Result<Scenario, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut scenario: Scenario =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+        scenario.dir = Path::new(path)
+            .parent()
+            .unwrap_or(Path::new("."))
+            .to_path_buf();
+        if scenario.layout.is_some() && scenario.network.is_some() {
+            return Err(format!("{}: give a layout or a network, not both", path));
+        }
+        Ok(scenario)
+    }
+
+    // the road the scenario asks for, None to keep the one given on the command line
+    pub fn road(&self) -> Result<Option<Network>, String> {
+        let file = |name: &str| self.dir.join(name).to_string_lossy().into_owned();
+        if let Some(name) = &self.layout {
+            let layout = if LAYOUT_NAMES.contains(&name.as_str()) {
+                Layout::from_arg(name)?
+            } else {
+                Layout::load(&file(name))?
+            };
+            return Ok(Some(Network::single(layout)));
+        }
+        match &self.network {
+            Some(name) => Network::load(&file(name)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // checks every spawn against the network it will run on
+    pub fn schedule(&self, network: &Network) -> Result<Vec<Spawn>, String> {
+        let mut spawns = Vec::new();
+        for (i, s) in self.spawns.iter().enumerate() {
+            let spawn = resolve(s, network).map_err(|e| format!("spawn {}: {}", i + 1, e))?;
+            spawns.push(spawn);
+        }
+        Ok(spawns)
+    }
+
+    pub fn demand(&self, network: &Network) -> Result<Vec<Demand>, String> {
+        let mut demand = Vec::new();
+        for (i, d) in self.demand.iter().enumerate() {
+            let resolved =
+                resolve_demand(d, network).map_err(|e| format!("demand {}: {}", i + 1, e))?;
+            demand.push(resolved);
+        }
+        Ok(demand)
+    }
+}
+
+fn resolve(s: &SpawnFile, network: &Network) -> Result<Spawn, String> {
+    if !s.time.is_finite() || s.time < 0.0 {
+        return Err("time must be finite and not negative".to_string());
+    }
+    if s.speed
+        .is_some_and(|speed| !speed.is_finite() || speed < 0.0)
+    {
+        return Err("speed must be finite and not negative".to_string());
+    }
+    let origin = origin(network, s.from, &s.at)?;
+    let arm = network.nodes[origin.0]
+        .layout
+        .arm(origin.1)
+        .expect("origins have an arm");
+    if let Some(lane) = s.lane {
+        let Some(l) = arm.lanes.get(lane) else {
+            return Err(format!("the arm has no lane {}", lane));
+        };
+        if let Some(m) = s.movement
+            && !l.movements.contains(&m)
+        {
+            return Err(format!("lane {} does not allow {:?}", lane, m));
+        }
+    }
+    if let Some(m) = s.movement
+        && !arm.lanes.iter().any(|l| l.movements.contains(&m))
+    {
+        return Err(format!("no lane from {:?} allows {:?}", s.from, m));
+    }
+
+    let to = match &s.to {
+        Some(_) if network.links.is_empty() => {
+            return Err("to needs a network, a lone intersection takes a movement".to_string());
+        }
+        Some((name, side)) => {
+            let exit = (node_named(network, name)?, *side);
+            if !network.exits().contains(&exit) {
+                return Err(format!("{:?} {:?} is not an exit of the world", name, side));
+            }
+            Some(exit)
+        }
+        None => None,
+    };
+    Ok(Spawn {
+        time: s.time,
+        origin,
+        request: Request {
+            movement: s.movement,
+            lane: s.lane,
+            to,
+            vehicle: s.vehicle,
+            speed: s.speed,
+        },
+    })
+}
+
+fn resolve_demand(d: &DemandFile, network: &Network) -> Result<Demand, String> {
+    let profile = match (d.rate, &d.profile) {
+        (Some(rate), None) => vec![(0.0, rate)],
+        (None, Some(points)) if !points.is_empty() => points.clone(),
+        _ => return Err("give either a rate or a profile with points".to_string()),
+    };
+    if profile
+        .iter()
+        .any(|&(t, rate)| !(t.is_finite() && t >= 0.0 && rate.is_finite() && rate >= 0.0))
+    {
+        return Err("times and rates must be finite and not negative".to_string());
+    }
+    if profile.windows(2).any(|w| w[1].0 <= w[0].0) {
+        return Err("profile times must go up".to_string());
+    }
+    if let Some(p) = d.platoon
+        && (p.size == 0 || !(p.headway.is_finite() && p.headway > 0.0))
+    {
+        return Err("a platoon needs at least one car and a positive, finite headway".to_string());
+    }
+
+    let origin = origin(network, d.from, &d.at)?;
+    let movements = network.nodes[origin.0].layout.movements_from(origin.1);
+    if let Some(t) = d.turning {
+        t.check(&movements)?;
+    }
+    Ok(Demand {
+        origin,
+        profile,
+        platoon: d.platoon,
+        turning: d.turning,
+        movements,
+    })
+}
+
+// the arm of the named intersection, or the first on that side the world is entered on
+fn origin(network: &Network, from: Direction, at: &Option<String>) -> Result<Approach, String> {
+    let origins = network.origins();
+    match at {
+        Some(name) => {
+            let node = node_named(network, name)?;
+            Some((node, from)).filter(|o| origins.contains(o))
+        }
+        None => origins.into_iter().find(|o| o.1 == from),
+    }
+    .ok_or(format!("no lanes enter the world from {:?}", from))
+}
+
+fn node_named(network: &Network, name: &str) -> Result<usize, String> {
+    network
+        .nodes
+        .iter()
+        .position(|n| n.name == name)
+        .ok_or(format!("unknown intersection {:?}", name))
+}
@@ -0,0 +1,586 @@
+use std::collections::HashMap;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::air::Airspace;
+use crate::car::{Car, Direction};
+use crate::collision::{Collision, CollisionDetector};
+use crate::conflicts::{ConflictTable, SWEPT_WIDTH};
+use crate::demand::Demand;
+use crate::following::Idm;
+use crate::layout::{Layout, Point};
+use crate::network::{Approach, Hop, Network};
+use crate::pedestrian::{self, Crosswalk, Pedestrian};
+use crate::policy::{self, Access, IntersectionPolicy, PolicyContext};
+use crate::scenario::Spawn;
+use crate::spawn_cars::{Request, TurnRadii, TurningRatios, continue_trip, spawn_car};
+use crate::trip::{Trip, percentile};
+use crate::vehicle::VehicleType;
+
+// fixed step of the simulation clock, all times below are simulated seconds
+pub const DT: f64 = 1.0 / 60.0;
+const SPAWN_COOLDOWN: f64 = 0.25;
+const AUTO_SPAWN_DURATION: f64 = 60.0;
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub spawned: usize,
+    // the trip record of every vehicle spawned, by id
+    pub trips: HashMap<usize, Trip>,
+    pub close_calls: usize,
+    pub collisions: Vec<Collision>,
+    // cars that waited off screen for their entry to clear
+    pub queued: usize,
+    // longest queue any entry lane had
+    pub max_queue: usize,
+    // seconds waited in the entry queues, summed over the cars
+    pub queue_time: f64,
+    // pedestrians that came to a crosswalk and those that made it across
+    pub pedestrians: usize,
+    pub crossed: usize,
+    // seconds each pedestrian let across had waited at the kerb
+    pub pedestrian_waits: Vec<f64>,
+    // vehicles running into pedestrians, ids are (car, pedestrian)
+    pub pedestrian_collisions: Vec<Collision>,
+}
+
+impl Stats {
+    // trips of the vehicles that drove off the map
+    pub fn finished(&self) -> impl Iterator<Item = &Trip> + '_ {
+        self.trips.values().filter(|trip| trip.finished.is_some())
+    }
+
+    fn durations(&self) -> impl Iterator<Item = f64> + '_ {
+        self.finished().filter_map(Trip::duration)
+    }
+
+    pub fn max_duration(&self) -> f64 {
+        self.durations().fold(0.0, f64::max)
+    }
+
+    pub fn min_duration(&self) -> Option<f64> {
+        self.durations().reduce(f64::min)
+    }
+
+    // mean delay of the finished emergency vehicles, or of the other traffic
+    pub fn mean_delay(&self, emergency: bool) -> Option<f64> {
+        self.mean_delay_of(|trip| trip.vehicle.is_emergency() == emergency)
+    }
+
+    // mean delay of the finished cyclists, or of the motor traffic
+    pub fn mean_cyclist_delay(&self, cyclists: bool) -> Option<f64> {
+        self.mean_delay_of(|trip| (trip.vehicle == VehicleType::Bicycle) == cyclists)
+    }
+
+    // mean delay of the finished human-driven vehicles, or of the automated ones
+    pub fn mean_human_delay(&self, humans: bool) -> Option<f64> {
+        self.mean_delay_of(|trip| trip.human == humans)
+    }
+
+    // mean delay of the finished vehicles the test holds for
+    pub fn mean_delay_of(&self, keep: impl Fn(&Trip) -> bool) -> Option<f64> {
+        mean(
+            self.finished()
+                .filter(|trip| keep(trip))
+                .map(|trip| trip.delay),
+        )
+    }
+
+    // delay pct percent of the finished vehicles stayed within, 50 for the median
+    pub fn delay_percentile(&self, pct: f64) -> Option<f64> {
+        let delays: Vec<f64> = self.finished().map(|trip| trip.delay).collect();
+        percentile(&delays, pct)
+    }
+
+    // finished vehicles the test holds for
+    pub fn count(&self, keep: impl Fn(&Trip) -> bool) -> usize {
+        self.finished().filter(|trip| keep(trip)).count()
+    }
+
+    // mean over the finished vehicles of the speed each kept on the road
+    pub fn mean_speed(&self) -> Option<f64> {
+        mean(self.finished().map(Trip::mean_speed))
+    }
+
+    pub fn max_speed(&self) -> f64 {
+        self.finished()
+            .map(|trip| trip.max_speed)
+            .fold(0.0, f64::max)
+    }
+
+    // stops a finished vehicle came to and seconds it stood, on average
+    pub fn mean_stops(&self) -> Option<(f64, f64)> {
+        let stops = mean(self.finished().map(|trip| trip.stops as f64))?;
+        let stopped = mean(self.finished().map(|trip| trip.stopped))?;
+        Some((stops, stopped))
+    }
+
+    // Finished vehicles that drove through each approach of each intersection, by the
+    // movement they took there. A trip across a network counts at every intersection
+    // on its route. Sorted by intersection, approach and movement.
+    pub fn throughput(&self) -> Vec<(Hop, usize)> {
+        let mut counts: HashMap<Hop, usize> = HashMap::new();
+        for hop in self.finished().flat_map(|trip| &trip.route) {
+            *counts.entry(*hop).or_default() += 1;
+        }
+        let mut counts: Vec<(Hop, usize)> = counts.into_iter().collect();
+        counts.sort_by_key(|(hop, _)| (hop.node, hop.from as usize, hop.movement as usize));
+        counts
+    }
+
+    pub fn mean_pedestrian_wait(&self) -> Option<f64> {
+        mean(self.pedestrian_waits.iter().copied())
+    }
+
+    pub fn max_pedestrian_wait(&self) -> f64 {
+        self.pedestrian_waits.iter().copied().fold(0.0, f64::max)
+    }
+
+    // mean number of cars waiting in the entry queues over the run
+    pub fn mean_queue(&self, time: f64) -> f64 {
+        if time > 0.0 {
+            self.queue_time / time
+        } else {
+            0.0
+        }
+    }
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, n) = values.fold((0.0, 0), |(sum, n), v| (sum + v, n + 1));
+    (n > 0).then(|| sum / n as f64)
+}
+
+// what runs one intersection of the network
+struct Manager {
+    policy: Box<dyn IntersectionPolicy>,
+    conflicts: ConflictTable,
+}
+
+// World state of the network, no rendering in here so it can run headless.
+// Advances by a fixed DT per tick and draws every random choice from one seeded rng,
+// so the same seed gives the same run on any machine.
+pub struct Simulation {
+    pub cars: Vec<Car>,
+    pub stats: Stats,
+    pub time: f64,
+    pub seed: u64,
+    // stop ticking as soon as two vehicles overlap, so the state can be inspected
+    pub pause_on_collision: bool,
+    pub paused: bool,
+    // car following parameters given to every spawned car
+    pub following: Idm,
+    // movements of cars spawned without one, may be changed while running
+    pub turning: TurningRatios,
+    // intersections clear a path for emergency vehicles
+    pub preemption: bool,
+    pub network: Network,
+    // planes flying over it all
+    pub air: Airspace,
+    // pedestrians at the crosswalks and how many come to them per minute
+    pub pedestrians: Vec<Pedestrian>,
+    pub pedestrian_rate: f64,
+    // percent of the cars spawned without a vehicle type that are bicycles
+    pub cyclists: f64,
+    // percent of the cars spawned that a human drives
+    pub humans: f64,
+    pub crosswalks: Vec<Crosswalk>,
+    rng: StdRng,
+    // pedestrians arrive from a generator of their own, so the cars of a seed arrive
+    // the same with or without them
+    walker_rng: StdRng,
+    // for intersections whose node does not name one
+    policy: String,
+    managers: Vec<Manager>,
+    turn_radii: TurnRadii,
+    collisions: CollisionDetector,
+    next_id: usize,
+    last_spawn_time: Option<f64>,
+    auto_spawn_start: Option<f64>,
+    // scripted spawns still to come, latest first
+    schedule: Vec<Spawn>,
+    // cars waiting off screen for their entry lane to clear, in the order they came
+    queue: Vec<Car>,
+    demand: Vec<Demand>,
+}
+
+impl Simulation {
+    // the policy name must be one of policy::POLICY_NAMES
+    pub fn new(seed: u64, policy: &str) -> Self {
+        let mut sim = Simulation {
+            cars: Vec::new(),
+            stats: Stats::default(),
+            time: 0.0,
+            seed,
+            pause_on_collision: false,
+            paused: false,
+            following: Idm::default(),
+            turning: TurningRatios::default(),
+            preemption: true,
+            network: Network::single(Layout::default()),
+            air: Airspace::new(0.0, 0.0, seed),
+            pedestrians: Vec::new(),
+            pedestrian_rate: 0.0,
+            cyclists: 0.0,
+            humans: 0.0,
+            rng: StdRng::seed_from_u64(seed),
+            walker_rng: StdRng::seed_from_u64(seed.wrapping_add(2)),
+            crosswalks: Vec::new(),
+            policy: policy.to_string(),
+            managers: Vec::new(),
+            turn_radii: TurnRadii::default(),
+            collisions: CollisionDetector::default(),
+            next_id: 0,
+            last_spawn_time: None,
+            auto_spawn_start: None,
+            schedule: Vec::new(),
+            queue: Vec::new(),
+            demand: Vec::new(),
+        };
+        sim.set_network(Network::single(Layout::default()));
+        sim
+    }
+
+    pub fn tick(&mut self) {
+        if self.paused {
+            return;
+        }
+        self.time += DT;
+        let now = self.time;
+
+        let snapshot = self.cars.clone();
+        // each manager only sees the cars at its own intersection
+        let mut at_node: Vec<Vec<Car>> = vec![Vec::new(); self.managers.len()];
+        for car in &snapshot {
+            at_node[car.node].push(car.clone());
+        }
+        let mut walkers = self.walkers_at_nodes();
+        for ((manager, cars), walkers) in self.managers.iter_mut().zip(&at_node).zip(&walkers) {
+            let ctx = PolicyContext {
+                time: now,
+                conflicts: &manager.conflicts,
+                walkers,
+            };
+            manager.policy.update(cars, &ctx);
+        }
+        // pedestrians are let across before the cars move, so none drives onto a
+        // crosswalk a pedestrian has just been let onto
+        for walker in self.pedestrians.iter_mut().filter(|w| !w.walking) {
+            let node = walker.crosswalk.node;
+            let manager = &mut self.managers[node];
+            let ctx = PolicyContext {
+                time: now,
+                conflicts: &manager.conflicts,
+                walkers: &walkers[node],
+            };
+            if manager.policy.walk(walker, &at_node[node], &ctx) {
+                walker.walking = true;
+                self.stats.pedestrian_waits.push(now - walker.arrived);
+            }
+        }
+        walkers = self.walkers_at_nodes();
+        for car in self.cars.iter_mut() {
+            let manager = &mut self.managers[car.node];
+            let ctx = PolicyContext {
+                time: now,
+                conflicts: &manager.conflicts,
+                walkers: &walkers[car.node],
+            };
+            let cars = &at_node[car.node];
+            if car.human {
+                let access = if policy::exit_blocked(car, cars) {
+                    Access::Stop
+                } else {
+                    policy::human_access(car, cars, manager.policy.as_ref(), &ctx)
+                };
+                car.update_position(&snapshot, access, &mut self.stats.close_calls, DT);
+                continue;
+            }
+            // a human driver on its way across is out of reach of the manager, the
+            // car gives way to it before anything else, and no car drives into the box
+            // with nowhere to go on the other side
+            let overridden = policy::give_way_to_humans(car, cars, &ctx)
+                .or_else(|| policy::exit_blocked(car, cars).then_some(Access::Stop))
+                .or_else(|| {
+                    self.preemption
+                        .then(|| policy::preempt(car, cars, &ctx))
+                        .flatten()
+                });
+            let access = match overridden {
+                Some(Access::Stop) => {
+                    manager.policy.hold(car);
+                    Access::Stop
+                }
+                Some(access) => access,
+                None => manager.policy.access(car, cars, &ctx),
+            };
+            car.update_position(&snapshot, access, &mut self.stats.close_calls, DT);
+        }
+        let before = self.pedestrians.len();
+        let cars = &self.cars;
+        self.pedestrians.retain_mut(|walker| !walker.step(cars, DT));
+        self.stats.crossed += before - self.pedestrians.len();
+
+        let collisions = self.collisions.detect(&self.cars, now);
+        let struck = self
+            .collisions
+            .detect_pedestrians(&self.cars, &self.pedestrians, now);
+        if (!collisions.is_empty() || !struck.is_empty()) && self.pause_on_collision {
+            self.paused = true;
+        }
+        self.stats.collisions.extend(collisions);
+        self.stats.pedestrian_collisions.extend(struck);
+        for car in &self.cars {
+            if let Some(trip) = self.stats.trips.get_mut(&car.id) {
+                trip.record(car, now, DT);
+            }
+        }
+
+        for car in self.cars.iter_mut() {
+            if car.has_finished() {
+                continue_trip(&self.network, car, &self.turn_radii);
+            }
+        }
+        for car in self.cars.iter().filter(|car| car.has_finished()) {
+            if let Some(trip) = self.stats.trips.get_mut(&car.id) {
+                trip.finish(car, now);
+            }
+        }
+        self.cars.retain(|car| !car.has_finished());
+
+        // queued cars drive in once their entry is clear, in order for each lane: a
+        // cyclist at the kerb may find room where the car waiting before it does not
+        let mut i = 0;
+        while i < self.queue.len() {
+            let first = !self.queue[..i]
+                .iter()
+                .any(|c| c.enters_with(&self.queue[i]));
+            if first && self.entry_clear(&self.queue[i]) {
+                let mut car = self.queue.remove(i);
+                // the wait off screen was all delay
+                car.delay += self
+                    .stats
+                    .trips
+                    .get(&car.id)
+                    .map_or(0.0, |trip| now - trip.spawned);
+                self.cars.push(car);
+            } else {
+                i += 1;
+            }
+        }
+
+        for d in &self.demand {
+            for spawn in d.arrivals(now, &mut self.rng) {
+                let at = self.schedule.partition_point(|s| s.time > spawn.time);
+                self.schedule.insert(at, spawn);
+            }
+        }
+        // scripted and generated cars come in on time, whatever the cooldown says
+        while self.schedule.last().is_some_and(|s| s.time <= now) {
+            let spawn = self.schedule.pop().expect("checked above");
+            self.push_car(spawn.origin, &spawn.request);
+        }
+
+        if let Some(start) = self.auto_spawn_start {
+            if now - start < AUTO_SPAWN_DURATION {
+                let origins = self.network.origins();
+                if self.cooldown_elapsed(now, SPAWN_COOLDOWN * 2.0) && !origins.is_empty() {
+                    let origin = origins[self.rng.gen_range(0..origins.len())];
+                    self.push_car(origin, &Request::default());
+                }
+            } else {
+                self.auto_spawn_start = None;
+            }
+        }
+
+        self.air.tick(DT);
+        if self.pedestrian_rate > 0.0
+            && !self.crosswalks.is_empty()
+            && self
+                .walker_rng
+                .gen_bool((self.pedestrian_rate / 60.0 * DT).min(1.0))
+        {
+            let i = self.walker_rng.gen_range(0..self.crosswalks.len());
+            let walker = Pedestrian::new(
+                self.stats.pedestrians,
+                self.crosswalks[i].clone(),
+                now,
+                &mut self.walker_rng,
+            );
+            self.pedestrians.push(walker);
+            self.stats.pedestrians += 1;
+        }
+
+        let longest = self.queue_lengths().into_iter().map(|(_, n)| n).max();
+        self.stats.max_queue = self.stats.max_queue.max(longest.unwrap_or(0));
+        self.stats.queue_time += self.queue.len() as f64 * DT;
+    }
+
+    // cars waiting at each entry lane that has a queue, by where the first of them starts
+    pub fn queue_lengths(&self) -> Vec<(Point, usize)> {
+        let mut lengths: Vec<(Point, usize)> = Vec::new();
+        let mut firsts: Vec<&Car> = Vec::new();
+        for car in &self.queue {
+            match firsts.iter().position(|first| first.enters_with(car)) {
+                Some(k) => lengths[k].1 += 1,
+                None => {
+                    firsts.push(car);
+                    lengths.push((car.position, 1));
+                }
+            }
+        }
+        lengths
+    }
+
+    // the conflict tables depend on the shape of the turns, so they are rebuilt here
+    pub fn set_turn_radii(&mut self, radii: TurnRadii) {
+        self.turn_radii = radii;
+        for (manager, node) in self.managers.iter_mut().zip(&self.network.nodes) {
+            manager.conflicts = ConflictTable::new(&node.layout, SWEPT_WIDTH, &radii);
+        }
+    }
+
+    // every intersection gets a manager of its own
+    pub fn set_network(&mut self, network: Network) {
+        self.air.set_bounds(network.width, network.height);
+        self.crosswalks = pedestrian::crosswalks(&network);
+        self.network = network;
+        self.managers = self
+            .network
+            .nodes
+            .iter()
+            .map(|node| Manager {
+                policy: policy::from_name(
+                    node.policy.as_deref().unwrap_or(&self.policy),
+                    &node.layout,
+                )
+                .expect("policy names are checked when they are read"),
+                conflicts: ConflictTable::new(&node.layout, SWEPT_WIDTH, &self.turn_radii),
+            })
+            .collect();
+    }
+
+    // names of the policies in use, one per intersection unless they all run the same
+    pub fn policy_name(&self) -> String {
+        let mut names: Vec<&str> = self.managers.iter().map(|m| m.policy.name()).collect();
+        names.dedup();
+        names.join(", ")
+    }
+
+    pub fn start_auto_spawn(&mut self) {
+        self.auto_spawn_start = Some(self.time);
+    }
+
+    // spawns of a scenario, each is made once the clock reaches its time
+    pub fn set_schedule(&mut self, mut spawns: Vec<Spawn>) {
+        spawns.sort_by(|a, b| b.time.total_cmp(&a.time));
+        self.schedule = spawns;
+    }
+
+    pub fn set_demand(&mut self, demand: Vec<Demand>) {
+        self.demand = demand;
+    }
+
+    pub fn is_auto_spawning(&self) -> bool {
+        self.auto_spawn_start.is_some()
+    }
+
+    // Manual spawn on the first arm at the edge of the world coming from that side,
+    // ignored while the cooldown is running
+    pub fn spawn(&mut self, direction: Direction) -> bool {
+        if !self.cooldown_elapsed(self.time, SPAWN_COOLDOWN) {
+            return false;
+        }
+        match self
+            .network
+            .origins()
+            .into_iter()
+            .find(|o| o.1 == direction)
+        {
+            Some(origin) => self.push_car(origin, &Request::default()),
+            None => false,
+        }
+    }
+
+    // a plane on one of the flight paths, false if the sky at its entry is full
+    pub fn spawn_plane(&mut self) -> bool {
+        self.air.spawn()
+    }
+
+    // the pedestrians at each intersection, indexed like the managers
+    fn walkers_at_nodes(&self) -> Vec<Vec<Pedestrian>> {
+        let mut at_node = vec![Vec::new(); self.managers.len()];
+        for walker in &self.pedestrians {
+            at_node[walker.crosswalk.node].push(walker.clone());
+        }
+        at_node
+    }
+
+    // Nothing stands on the spot where the lane starts, and the car could drive in
+    // there and still stop behind the car ahead.
+    fn entry_clear(&self, car: &Car) -> bool {
+        let on_spot = self
+            .cars
+            .iter()
+            .any(|c| car.distance_to(c.position) < (car.length() + c.length()) / 2.0);
+        let too_close = car.car_in_front(&self.cars).is_some_and(|front| {
+            car.gap_to(front.position, front.length()) < car.idm.min_gap + car.stopping_distance()
+        });
+        !on_spot && !too_close
+    }
+
+    fn cooldown_elapsed(&self, now: f64, cooldown: f64) -> bool {
+        self.last_spawn_time
+            .is_none_or(|last| now - last >= cooldown)
+    }
+
+    fn push_car(&mut self, origin: Approach, request: &Request) -> bool {
+        let now = self.time;
+        let mut request = *request;
+        if request.movement.is_none()
+            && let Some(turning) = self.turning.for_side(origin.1)
+        {
+            let allowed = self.network.nodes[origin.0].layout.movements_from(origin.1);
+            request.movement = turning.pick(&allowed, &mut self.rng);
+        }
+        if request.vehicle.is_none()
+            && self.cyclists > 0.0
+            && self.rng.gen_bool(self.cyclists / 100.0)
+        {
+            request.vehicle = Some(VehicleType::Bicycle);
+        }
+        match spawn_car(
+            &self.network,
+            origin,
+            &request,
+            self.next_id,
+            &self.turn_radii,
+            &mut self.rng,
+        ) {
+            Some(mut car) => {
+                car.idm = self.following;
+                // police cars answer to preemption and bicycles ride as they do, neither
+                // is handed to a human driver
+                car.human = self.humans > 0.0
+                    && !car.vehicle.is_emergency()
+                    && car.vehicle != VehicleType::Bicycle
+                    && self.rng.gen_bool(self.humans / 100.0);
+                self.stats.trips.insert(self.next_id, Trip::new(&car, now));
+                // a car arriving on a blocked lane waits off screen, behind any already waiting
+                let waiting = self.queue.iter().any(|c| c.enters_with(&car));
+                if waiting || !self.entry_clear(&car) {
+                    self.stats.queued += 1;
+                    self.queue.push(car);
+                } else {
+                    self.cars.push(car);
+                }
+                self.next_id += 1;
+                self.stats.spawned = self.next_id;
+                self.last_spawn_time = Some(now);
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -1,210 +1,399 @@
-    use sdl2::keyboard::Keycode;
-    use sdl2::render::Texture;
-
-    use crate::{Car, Direction, Lane, Waypoint};
-    use rand::Rng;
-
-    fn random_lane() -> Lane {
-        match rand::thread_rng().gen_range(0..=2) {
-            0 => Lane::Straight,
-            1 => Lane::Right,
-            2 => Lane::Left,
-            _ => unreachable!(),
+use crate::car::{Car, Direction, Lane, Movement, ROAD_SPEED, Waypoint, shift_path};
+use crate::layout::{Arm, LANE_CHANGE_LENGTH, Layout, MERGE_LOOKAHEAD, right_of};
+use crate::network::{Approach, Hop, Network};
+use crate::vehicle::VehicleType;
+use rand::Rng;
+use serde::Deserialize;
+
+// Share of the cars taking each movement. Only the ratios matter, so counts from a
+// real intersection can be used as they are.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Turning {
+    #[serde(default)]
+    pub left: f64,
+    #[serde(default)]
+    pub straight: f64,
+    #[serde(default)]
+    pub right: f64,
+}
+
+impl Turning {
+    pub fn share(&self, movement: Movement) -> f64 {
+        match movement {
+            Movement::Left => self.left,
+            Movement::Straight => self.straight,
+            Movement::Right => self.right,
         }
     }
-    pub fn spawn_car_from_key<'a>(
-        key: Keycode,
-        texture_pool: &'a [Texture<'a>],
-        id: usize,
-    ) -> Option<Car<'a>> {
-        let lane = random_lane();
-        let texture = &texture_pool[rand::thread_rng().gen_range(0..texture_pool.len())];
-
-        let (direction, position, waypoints) = match key {
-            Keycode::Left => {
-                let direction = Direction::East;
-                let (position, waypoints) = match lane {
-                    Lane::Straight => (
-                        (1600.0, 510.0),
-                        vec![Waypoint {
-                            x: -20.0,
-                            y: 510.0,
-                            angle: None,
-                        }],
-                    ),
-                    Lane::Left => (
-                        (1603.0, 570.0),
-                        vec![
-                            Waypoint {
-                                x: 773.0,
-                                y: 570.0,
-                                angle: Some(180.0),
-                            },
-                            Waypoint {
-                                x: 773.0,
-                                y: 1240.0,
-                                angle: None,
-                            },
-                        ],
-                    ),
-                    Lane::Right => (
-                        (1600.0, 450.0),
-                        vec![
-                            Waypoint {
-                                x: 950.0,
-                                y: 450.0,
-                                angle: Some(360.0),
-                            },
-                            Waypoint {
-                                x: 950.0,
-                                y: -40.0,
-                                angle: None,
-                            },
-                        ],
-                    ),
-                    _ => return None,
-                };
-                (direction, position, waypoints)
-            }
-            Keycode::Right => {
-                let direction = Direction::West;
-                let (position, waypoints) = match lane {
-                    Lane::Straight => (
-                        (0.0, 690.0),
-                        vec![Waypoint {
-                            x: 1620.0,
-                            y: 690.0,
-                            angle: None,
-                        }],
-                    ),
-                    Lane::Left => (
-                        (0.0, 630.0),
-                        vec![
-                            Waypoint {
-                                x: 830.0,
-                                y: 630.0,
-                                angle: Some(360.0),
-                            },
-                            Waypoint {
-                                x: 830.0,
-                                y: -40.0,
-                                angle: None,
-                            },
-                        ],
-                    ),
-                    Lane::Right => (
-                        (0.0, 750.0),
-                        vec![
-                            Waypoint {
-                                x: 650.0,
-                                y: 750.0,
-                                angle: Some(180.0),
-                            },
-                            Waypoint {
-                                x: 650.0,
-                                y: 1240.0,
-                                angle: None,
-                            },
-                        ],
-                    ),
-                    _ => return None,
-                };
-                (direction, position, waypoints)
+
+    pub fn check(&self, allowed: &[Movement]) -> Result<(), String> {
+        if [self.left, self.straight, self.right]
+            .iter()
+            .any(|&share| !(share.is_finite() && share >= 0.0))
+        {
+            return Err("turning shares must be finite and not negative".to_string());
+        }
+        let total: f64 = allowed.iter().map(|&m| self.share(m)).sum();
+        if total == 0.0 {
+            return Err("none of the movements there has a turning share".to_string());
+        }
+        if !total.is_finite() {
+            return Err("turning shares are too large to add up".to_string());
+        }
+        Ok(())
+    }
+
+    // a movement by the ratios among the ones allowed, None if none of them has a share
+    pub fn pick(&self, allowed: &[Movement], rng: &mut impl Rng) -> Option<Movement> {
+        let total: f64 = allowed.iter().map(|&m| self.share(m)).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut left = rng.gen_range(0.0..total);
+        for &m in allowed {
+            left -= self.share(m);
+            if left < 0.0 {
+                return Some(m);
             }
-            Keycode::Up => {
-                let direction = Direction::South;
-                let (position, waypoints) = match lane {
-                    Lane::Straight => (
-                        (890.0, 1200.0),
-                        vec![Waypoint {
-                            x: 890.0,
-                            y: -20.0,
-                            angle: None,
-                        }],
-                    ),
-                    Lane::Left => (
-                        (830.0, 1200.0),
-                        vec![
-                            Waypoint {
-                                x: 830.0,
-                                y: 570.0,
-                                angle: Some(270.0),
-                            },
-                            Waypoint {
-                                x: -40.0,
-                                y: 570.0,
-                                angle: None,
-                            },
-                        ],
-                    ),
-                    Lane::Right => (
-                        (950.0, 1200.0),
-                        vec![
-                            Waypoint {
-                                x: 950.0,
-                                y: 750.0,
-                                angle: Some(90.0),
-                            },
-                            Waypoint {
-                                x: 1640.0,
-                                y: 750.0,
-                                angle: None,
-                            },
-                        ],
-                    ),
-                    _ => return None,
-                };
-                (direction, position, waypoints)
+        }
+        allowed.iter().rev().copied().find(|&m| self.share(m) > 0.0)
+    }
+}
+
+// Turning shares of the cars spawned without a movement, by the side they come from.
+// Sides without shares of their own use `all`, and without that a car takes a random
+// lane and one of its movements.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TurningRatios {
+    pub all: Option<Turning>,
+    pub north: Option<Turning>,
+    pub south: Option<Turning>,
+    pub east: Option<Turning>,
+    pub west: Option<Turning>,
+}
+
+impl TurningRatios {
+    pub fn for_side(&self, from: Direction) -> Option<Turning> {
+        let side = match from {
+            Direction::North => self.north,
+            Direction::South => self.south,
+            Direction::East => self.east,
+            Direction::West => self.west,
+        };
+        side.or(self.all)
+    }
+
+    // None sets the shares of every side
+    pub fn set(&mut self, from: Option<Direction>, turning: Turning) {
+        match from {
+            None => {
+                *self = TurningRatios {
+                    all: Some(turning),
+                    ..TurningRatios::default()
+                }
             }
-            Keycode::Down => {
-                let direction = Direction::North;
-                let (position, waypoints) = match lane {
-                    Lane::Straight => (
-                        (710.0, 0.0),
-                        vec![Waypoint {
-                            x: 710.0,
-                            y: 1220.0,
-                            angle: None,
-                        }],
-                    ),
-                    Lane::Left => (
-                        (773.0, 0.0),
-                        vec![
-                            Waypoint {
-                                x: 773.0,
-                                y: 630.0,
-                                angle: Some(90.0),
-                            },
-                            Waypoint {
-                                x: 1640.0,
-                                y: 630.0,
-                                angle: None,
-                            },
-                        ],
-                    ),
-                    Lane::Right => (
-                        (650.0, 0.0),
-                        vec![
-                            Waypoint {
-                                x: 650.0,
-                                y: 450.0,
-                                angle: Some(270.0),
-                            },
-                            Waypoint {
-                                x: -40.0,
-                                y: 450.0,
-                                angle: None,
-                            },
-                        ],
-                    ),
-                    _ => return None,
-                };
-                (direction, position, waypoints)
+            Some(Direction::North) => self.north = Some(turning),
+            Some(Direction::South) => self.south = Some(turning),
+            Some(Direction::East) => self.east = Some(turning),
+            Some(Direction::West) => self.west = Some(turning),
+        }
+    }
+
+    // every arm cars enter the world on must have a share for a movement it allows
+    pub fn check(&self, network: &Network) -> Result<(), String> {
+        for (node, from) in network.origins() {
+            if let Some(turning) = self.for_side(from) {
+                let allowed = network.nodes[node].layout.movements_from(from);
+                turning
+                    .check(&allowed)
+                    .map_err(|e| format!("turning from {:?}: {}", from, e))?;
             }
-            _ => return None,
+        }
+        Ok(())
+    }
+}
+
+// What a spawn asks for. Anything left as None is drawn from the rng, so the
+// default is a fully random car.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Request {
+    pub movement: Option<Movement>,
+    pub lane: Option<usize>,
+    // exit of the network the trip goes to
+    pub to: Option<Approach>,
+    pub vehicle: Option<VehicleType>,
+    // initial speed, the road speed if None
+    pub speed: Option<f64>,
+}
+
+// a random lane of the arm that fits the request and one of the movements allowed
+// from it, None if the layout has no such lane coming from that side
+fn random_lane(
+    layout: &Layout,
+    direction: Direction,
+    request: &Request,
+    rng: &mut impl Rng,
+) -> Option<Lane> {
+    let lanes = &layout.arm(direction)?.lanes;
+    let fits: Vec<usize> = (0..lanes.len())
+        .filter(|&i| request.lane.is_none_or(|lane| lane == i))
+        .filter(|&i| {
+            request
+                .movement
+                .is_none_or(|m| lanes[i].movements.contains(&m))
+        })
+        .collect();
+    if fits.is_empty() {
+        return None;
+    }
+    let index = fits[rng.gen_range(0..fits.len())];
+    let movements = &lanes[index].movements;
+    let movement = match (request.movement, movements.len()) {
+        (Some(movement), _) => movement,
+        (None, 1) => movements[0],
+        (None, n) => movements[rng.gen_range(0..n)],
+    };
+    Some(Lane { index, movement })
+}
+
+// a random exit the origin leads to and the trip there, both as the request allows
+fn random_trip(
+    network: &Network,
+    origin: Approach,
+    request: &Request,
+    radii: &TurnRadii,
+    rng: &mut impl Rng,
+) -> Option<Vec<Hop>> {
+    let mut trips: Vec<Vec<Hop>> = network
+        .exits()
+        .into_iter()
+        .filter(|&exit| request.to.is_none_or(|to| to == exit))
+        .filter_map(|exit| network.plan(origin, exit, radii))
+        .filter(|trip| request.movement.is_none_or(|m| trip[0].movement == m))
+        .collect();
+    if trips.is_empty() {
+        return None;
+    }
+    Some(trips.swap_remove(rng.gen_range(0..trips.len())))
+}
+
+pub fn spawn_car(
+    network: &Network,
+    (node, direction): Approach,
+    request: &Request,
+    id: usize,
+    radii: &TurnRadii,
+    rng: &mut impl Rng,
+) -> Option<Car> {
+    let layout = &network.nodes[node].layout;
+    // a lone intersection has no trips to plan, the car just takes a random lane
+    let (lane, trip) = if network.links.is_empty() {
+        (random_lane(layout, direction, request, rng)?, Vec::new())
+    } else {
+        let mut trip = random_trip(network, (node, direction), request, radii, rng)?;
+        let first = trip.remove(0);
+        let lanes: Vec<usize> = lanes_for(layout, first)
+            .into_iter()
+            .filter(|&i| request.lane.is_none_or(|lane| lane == i))
+            .collect();
+        if lanes.is_empty() {
+            return None;
+        }
+        let index = lanes[rng.gen_range(0..lanes.len())];
+        let lane = Lane {
+            index,
+            movement: first.movement,
+        };
+        (lane, trip)
+    };
+    let vehicle = match request.vehicle {
+        Some(vehicle) => vehicle,
+        None => VehicleType::random(rng),
+    };
+    let (position, waypoints) = layout.route(direction, lane, radii)?;
+    let side = riding_side(layout, layout.arm(direction)?, lane, vehicle);
+    let (position, waypoints) = shift_path(position, &waypoints, side);
+
+    let mut car = Car::new(
+        lane,
+        position,
+        waypoints,
+        ROAD_SPEED,
+        id,
+        direction,
+        layout.intersection(),
+        0,
+    );
+    car.set_vehicle(vehicle);
+    car.side = side;
+    // the road speed stays the target, a slower start accelerates to it
+    car.speed = request.speed.unwrap_or(car.cruise_speed());
+    car.pocket = layout.pocket(direction, lane);
+    car.ring = layout.roundabout.map(|ring| (layout.center, ring.radius));
+    car.node = node;
+    car.trip = trip;
+    Some(car)
+}
+
+// Puts a car that reached the end of a link on its route through the next intersection
+// of its trip, in the lane for its movement that needs the fewest lane changes. Where
+// that is not the lane the car drives in, it moves over on the link and takes up both
+// lanes while it does, like a car bound for a turn pocket. False once the trip is over.
+pub fn continue_trip(network: &Network, car: &mut Car, radii: &TurnRadii) -> bool {
+    if car.trip.is_empty() {
+        return false;
+    }
+    let hop = car.trip.remove(0);
+    let layout = &network.nodes[hop.node].layout;
+    let Some(arm) = layout.arm(hop.from).filter(|arm| !arm.lanes.is_empty()) else {
+        return false;
+    };
+
+    // where the car is on the arm: distance out from the centre and lane, past the
+    // outermost lane where the link has more lanes than the arm takes in
+    let d = layout.inbound(hop.from);
+    let r = right_of(d);
+    let rel = (
+        car.position.0 - layout.center.0,
+        car.position.1 - layout.center.1,
+    );
+    let along = -(rel.0 * d.0 + rel.1 * d.1);
+    let width = layout.lane_width_of(arm);
+    let now = ((rel.0 * r.0 + rel.1 * r.1) / width).floor().max(0.0) as usize;
+
+    let entry = |i: usize| layout.entry_lane(arm, i).unwrap_or(i);
+    let Some(index) = lanes_for(layout, hop)
+        .into_iter()
+        .min_by_key(|&i| entry(i).abs_diff(now))
+    else {
+        return false;
+    };
+    let lane = Lane {
+        index,
+        movement: hop.movement,
+    };
+    let Some((_, mut waypoints)) = layout.route(hop.from, lane, radii) else {
+        return false;
+    };
+    let mut pocket = layout.pocket(hop.from, lane);
+    if entry(index) != now {
+        // keep to its lane for a bit first, so it can wait for a car beside it to pass
+        let reach = layout.box_reach(hop.from);
+        let crossed = entry(index).abs_diff(now) as f64;
+        let change = LANE_CHANGE_LENGTH * crossed;
+        let over = (along - MERGE_LOOKAHEAD - change).max(reach);
+        let (x, y) = layout.point_on(hop.from, (entry(index) as f64 + 0.5) * width, over);
+        waypoints.insert(0, Waypoint { x, y, angle: None });
+        if over + change < along {
+            let (x, y) = layout.point_on(hop.from, (now as f64 + 0.5) * width, over + change);
+            waypoints.insert(0, Waypoint { x, y, angle: None });
+        }
+        pocket = pocket.or(Some((now, over - reach)));
+    }
+    car.side = riding_side(layout, arm, lane, car.vehicle);
+    let (_, waypoints) = shift_path(car.position, &waypoints, car.side);
+    car.set_route(lane, hop.from, d, layout.intersection(), waypoints);
+    car.pocket = pocket;
+    car.ring = layout.roundabout.map(|ring| (layout.center, ring.radius));
+    car.node = hop.node;
+    true
+}
+
+// Px right of its lane's middle a vehicle keeps to. Only the outermost lane runs along
+// the kerb, in any other a cyclist keeps the middle and clear of the lane beside it. The
+// side holds for the whole route, so it rides at the kerb only where it also leaves in
+// the outermost exit lane. A roundabout's ring leaves no room to pass, there it keeps
+// the middle too.
+fn riding_side(layout: &Layout, arm: &Arm, lane: Lane, vehicle: VehicleType) -> f64 {
+    let exits_outermost = layout
+        .exit_arm(arm, lane.movement)
+        .zip(layout.exit_lane(arm, lane.index, lane.movement))
+        .is_some_and(|(exit, j)| j + 1 >= exit.exit_lanes);
+    if lane.index + 1 < arm.lanes.len() || !exits_outermost || layout.roundabout.is_some() {
+        return 0.0;
+    }
+    vehicle.side(layout.lane_width_of(arm))
+}
+
+// inbound lanes a hop's movement may be taken from
+fn lanes_for(layout: &Layout, hop: Hop) -> Vec<usize> {
+    layout.arm(hop.from).map_or(Vec::new(), |arm| {
+        (0..arm.lanes.len())
+            .filter(|&i| arm.lanes[i].movements.contains(&hop.movement))
+            .collect()
+    })
+}
+
+// how far before and after the corner a turn starts bending, in px
+#[derive(Debug, Clone, Copy)]
+pub struct TurnRadii {
+    pub right: f64,
+    pub left: f64,
+}
+
+impl Default for TurnRadii {
+    fn default() -> Self {
+        TurnRadii {
+            right: 50.0,
+            left: 120.0,
+        }
+    }
+}
+
+impl TurnRadii {
+    pub fn for_movement(&self, movement: Movement) -> f64 {
+        match movement {
+            Movement::Right => self.right,
+            Movement::Left => self.left,
+            Movement::Straight => 0.0,
+        }
+    }
+}
+
+// spacing of the points a curve is sampled at
+const CURVE_STEP: f64 = 10.0;
+
+// Replaces each corner of the path by a quadratic Bézier curve from `radius` px before
+// the corner to `radius` px after it, so cars drive a smooth arc instead of pivoting.
+pub fn round_corners(start: (f64, f64), corners: &[Waypoint], radius: f64) -> Vec<Waypoint> {
+    if radius <= 0.0 {
+        return corners.to_vec();
+    }
+
+    let mut waypoints = Vec::new();
+    let mut prev = start;
+    for (i, corner) in corners.iter().enumerate() {
+        let Some(next) = corners.get(i + 1) else {
+            waypoints.push(*corner);
+            break;
         };
 
-        Some(Car::new(
-            lane, position, waypoints, 5.0, id, direction, texture,None
-        ))
+        let (in_x, in_y) = (corner.x - prev.0, corner.y - prev.1);
+        let (out_x, out_y) = (next.x - corner.x, next.y - corner.y);
+        let in_len = (in_x * in_x + in_y * in_y).sqrt();
+        let out_len = (out_x * out_x + out_y * out_y).sqrt();
+        let r = radius.min(in_len).min(out_len / 2.0);
+
+        let from = (corner.x - in_x / in_len * r, corner.y - in_y / in_len * r);
+        let to = (
+            corner.x + out_x / out_len * r,
+            corner.y + out_y / out_len * r,
+        );
+        let samples = ((std::f64::consts::FRAC_PI_2 * r) / CURVE_STEP)
+            .ceil()
+            .max(2.0) as usize;
+        for k in 0..=samples {
+            let t = k as f64 / samples as f64;
+            let (a, b, c) = ((1.0 - t) * (1.0 - t), 2.0 * (1.0 - t) * t, t * t);
+            waypoints.push(Waypoint {
+                x: a * from.0 + b * corner.x + c * to.0,
+                y: a * from.1 + b * corner.y + c * to.1,
+                angle: None,
+            });
+        }
+        prev = to;
     }
+    waypoints
+}
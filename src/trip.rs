@@ -0,0 +1,188 @@
+use crate::car::{Car, Direction, Movement};
+use crate::network::Hop;
+use crate::vehicle::VehicleType;
+
+// Everything recorded about one vehicle from the moment it was asked for to the moment
+// it drove off the map. Times are simulated seconds. A car waiting off screen for its
+// entry to clear has been spawned but is not on the road yet, it drives no distance and
+// its wait there only shows in the delay.
+#[derive(Debug, Clone)]
+pub struct Trip {
+    pub id: usize,
+    pub vehicle: VehicleType,
+    pub human: bool,
+    // the intersections it was planned through, where it drove in and what it did there
+    pub route: Vec<Hop>,
+    pub spawned: f64,
+    // first time it was in an intersection box, and the last
+    pub entered: Option<f64>,
+    pub exited: Option<f64>,
+    // drove off the map
+    pub finished: Option<f64>,
+    // seconds on the road, of those standing still, how often it came to a stop and
+    // whether it stood at the last tick
+    pub on_road: f64,
+    pub stopped: f64,
+    pub stops: usize,
+    pub standing: bool,
+    // px driven and the fastest it went, in px/s
+    pub distance: f64,
+    pub max_speed: f64,
+    // seconds lost against driving the whole way at cruise speed, once finished
+    pub delay: f64,
+}
+
+impl Trip {
+    pub fn new(car: &Car, now: f64) -> Self {
+        let first = Hop {
+            node: car.node,
+            from: car.direction,
+            movement: car.lane.movement,
+        };
+        Trip {
+            id: car.id,
+            vehicle: car.vehicle,
+            human: car.human,
+            route: std::iter::once(first)
+                .chain(car.trip.iter().copied())
+                .collect(),
+            spawned: now,
+            entered: None,
+            exited: None,
+            finished: None,
+            on_road: 0.0,
+            stopped: 0.0,
+            stops: 0,
+            standing: false,
+            distance: 0.0,
+            max_speed: 0.0,
+            delay: 0.0,
+        }
+    }
+
+    // what the car did over the tick that ended at now
+    pub fn record(&mut self, car: &Car, now: f64, dt: f64) {
+        self.on_road += dt;
+        self.distance += car.speed * dt;
+        self.max_speed = self.max_speed.max(car.speed);
+        if car.is_waiting {
+            self.stopped += dt;
+            if !self.standing {
+                self.stops += 1;
+            }
+        }
+        self.standing = car.is_waiting;
+        if car.in_intersection() {
+            self.entered.get_or_insert(now);
+            self.exited = Some(now);
+        }
+    }
+
+    pub fn finish(&mut self, car: &Car, now: f64) {
+        self.finished = Some(now);
+        self.delay = car.delay;
+    }
+
+    // spawn to off the map, the wait in an entry queue included
+    pub fn duration(&self) -> Option<f64> {
+        self.finished.map(|finished| finished - self.spawned)
+    }
+
+    pub fn mean_speed(&self) -> f64 {
+        if self.on_road > 0.0 {
+            self.distance / self.on_road
+        } else {
+            0.0
+        }
+    }
+
+    pub const CSV_HEADER: &str = "id,vehicle,human,route,spawned,entered,exited,finished,\
+        stopped,stops,mean_speed,max_speed,delay";
+
+    // one line of the trip table, times left empty where the car never got that far;
+    // the route is node:from:movement for each intersection, separated by spaces
+    pub fn csv_row(&self) -> String {
+        let time = |t: Option<f64>| t.map_or(String::new(), |t| format!("{:.2}", t));
+        let route: Vec<String> = self
+            .route
+            .iter()
+            .map(|hop| {
+                format!(
+                    "{}:{}:{}",
+                    hop.node,
+                    direction_name(hop.from),
+                    movement_name(hop.movement)
+                )
+            })
+            .collect();
+        format!(
+            "{},{},{},{},{:.2},{},{},{},{:.2},{},{:.1},{:.1},{:.2}",
+            self.id,
+            format!("{:?}", self.vehicle).to_lowercase(),
+            self.human,
+            route.join(" "),
+            self.spawned,
+            time(self.entered),
+            time(self.exited),
+            time(self.finished),
+            self.stopped,
+            self.stops,
+            self.mean_speed(),
+            self.max_speed,
+            self.delay
+        )
+    }
+}
+
+// The value below which pct percent of the values lie, by nearest rank: the smallest
+// value with at least that share of them at or below it. None for no values.
+pub fn percentile(values: &[f64], pct: f64) -> Option<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let rank = (pct / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.clamp(1, sorted.len().max(1)) - 1).copied()
+}
+
+pub fn direction_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::North => "north",
+        Direction::South => "south",
+        Direction::East => "east",
+        Direction::West => "west",
+    }
+}
+
+pub fn movement_name(movement: Movement) -> &'static str {
+    match movement {
+        Movement::Left => "left",
+        Movement::Straight => "straight",
+        Movement::Right => "right",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentile_takes_the_nearest_rank() {
+        let values = [35.0, 20.0, 50.0, 15.0, 40.0];
+        assert_eq!(percentile(&values, 30.0), Some(20.0));
+        assert_eq!(percentile(&values, 40.0), Some(20.0));
+        assert_eq!(percentile(&values, 50.0), Some(35.0));
+        assert_eq!(percentile(&values, 95.0), Some(50.0));
+    }
+
+    #[test]
+    fn percentile_ends_at_the_smallest_and_largest_value() {
+        let values = [3.0, 1.0, 2.0];
+        assert_eq!(percentile(&values, 0.0), Some(1.0));
+        assert_eq!(percentile(&values, 100.0), Some(3.0));
+        assert_eq!(percentile(&[7.5], 50.0), Some(7.5));
+    }
+
+    #[test]
+    fn percentile_of_nothing_is_none() {
+        assert_eq!(percentile(&[], 50.0), None);
+    }
+}
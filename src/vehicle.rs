@@ -0,0 +1,171 @@
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::car::{INTERSECTION_SPEED, MAX_ACCEL, MAX_DECEL, VEHICLE_LENGTH, VEHICLE_WIDTH};
+
+// Kinds of road vehicle. The order is the order the renderer loads their sprites in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VehicleType {
+    Car,
+    Sports,
+    Police,
+    Bus,
+    Truck,
+    Motorcycle,
+    Bicycle,
+}
+
+// What sets a kind of vehicle apart. Length and width are of the body, which the car
+// following, the collision checks and the managers work with; the sprite is drawn
+// around it with the same margin the car sprite has.
+#[derive(Debug, Clone, Copy)]
+pub struct VehicleSpec {
+    pub length: f64,
+    pub width: f64,
+    // top speed in px/s, a vehicle never drives faster than the road allows either
+    pub max_speed: f64,
+    pub max_accel: f64,
+    pub max_decel: f64,
+    pub sprite: &'static str,
+    // colour the sprite is tinted with, white leaves it as it is
+    pub tint: (u8, u8, u8),
+}
+
+// a cyclist keeps this far from the kerb side edge of its lane
+const KERB_GAP: f64 = 3.0;
+
+// share of each kind in random traffic, cyclists come on top by a share of their own
+const MIX: [(VehicleType, f64); 6] = [
+    (VehicleType::Car, 60.0),
+    (VehicleType::Sports, 12.0),
+    (VehicleType::Police, 3.0),
+    (VehicleType::Bus, 5.0),
+    (VehicleType::Truck, 8.0),
+    (VehicleType::Motorcycle, 12.0),
+];
+
+impl VehicleType {
+    pub const ALL: [VehicleType; 7] = [
+        VehicleType::Car,
+        VehicleType::Sports,
+        VehicleType::Police,
+        VehicleType::Bus,
+        VehicleType::Truck,
+        VehicleType::Motorcycle,
+        VehicleType::Bicycle,
+    ];
+
+    pub fn spec(self) -> VehicleSpec {
+        match self {
+            VehicleType::Car => VehicleSpec {
+                length: VEHICLE_LENGTH,
+                width: VEHICLE_WIDTH,
+                max_speed: INTERSECTION_SPEED,
+                max_accel: MAX_ACCEL,
+                max_decel: MAX_DECEL,
+                sprite: "assets/Car.png",
+                tint: (255, 255, 255),
+            },
+            VehicleType::Sports => VehicleSpec {
+                length: 50.0,
+                width: 30.0,
+                max_speed: 560.0,
+                max_accel: 420.0,
+                max_decel: 700.0,
+                sprite: "assets/Black_viper.png",
+                tint: (255, 255, 255),
+            },
+            VehicleType::Police => VehicleSpec {
+                length: 52.0,
+                width: 30.0,
+                max_speed: 560.0,
+                max_accel: 400.0,
+                max_decel: 700.0,
+                sprite: "assets/Police.png",
+                tint: (255, 255, 255),
+            },
+            VehicleType::Bus => VehicleSpec {
+                length: 110.0,
+                width: 36.0,
+                max_speed: 360.0,
+                max_accel: 160.0,
+                max_decel: 400.0,
+                sprite: "assets/Car.png",
+                tint: (255, 200, 40),
+            },
+            VehicleType::Truck => VehicleSpec {
+                length: 90.0,
+                width: 36.0,
+                max_speed: 330.0,
+                max_accel: 140.0,
+                max_decel: 380.0,
+                sprite: "assets/Car.png",
+                tint: (150, 160, 190),
+            },
+            VehicleType::Motorcycle => VehicleSpec {
+                length: 30.0,
+                width: 14.0,
+                max_speed: 520.0,
+                max_accel: 450.0,
+                max_decel: 700.0,
+                sprite: "assets/Black_viper.png",
+                tint: (255, 110, 110),
+            },
+            VehicleType::Bicycle => VehicleSpec {
+                length: 28.0,
+                width: 12.0,
+                max_speed: 120.0,
+                max_accel: 120.0,
+                max_decel: 350.0,
+                sprite: "assets/Black_viper.png",
+                tint: (90, 220, 110),
+            },
+        }
+    }
+
+    // Px right of the middle of its lane the vehicle rides at. A cyclist keeps to the
+    // kerb side and leaves the rest of the lane, a wide one to pass it in.
+    pub fn side(self, lane_width: f64) -> f64 {
+        match self {
+            VehicleType::Bicycle => ((lane_width - self.spec().width) / 2.0 - KERB_GAP).max(0.0),
+            _ => 0.0,
+        }
+    }
+
+    // runs with lights and siren, intersections clear a path for it
+    pub fn is_emergency(self) -> bool {
+        self == VehicleType::Police
+    }
+
+    // index of its sprite in the pool the renderer loads
+    pub fn sprite(self) -> usize {
+        VehicleType::ALL
+            .iter()
+            .position(|&v| v == self)
+            .expect("every type is listed")
+    }
+
+    // a kind for random traffic, by the shares of the mix
+    pub fn random(rng: &mut impl Rng) -> VehicleType {
+        let total: f64 = MIX.iter().map(|(_, share)| share).sum();
+        let mut left = rng.gen_range(0.0..total);
+        for (vehicle, share) in MIX {
+            left -= share;
+            if left < 0.0 {
+                return vehicle;
+            }
+        }
+        VehicleType::Car
+    }
+}
+
+impl VehicleSpec {
+    // size the sprite is drawn at, the car sprite is 80x60 around a 50x30 body
+    pub fn sprite_size(&self) -> (u32, u32) {
+        (
+            (self.length * 1.6).round() as u32,
+            (self.width * 2.0).round() as u32,
+        )
+    }
+}
@@ -0,0 +1,92 @@
+use smart_road::layout::{LAYOUT_NAMES, Layout};
+use smart_road::network::Network;
+use smart_road::policy::POLICY_NAMES;
+use smart_road::simulation::{DT, Simulation};
+
+// long enough for all the random traffic to get through on any layout
+const SECONDS: f64 = 600.0;
+
+// Runs the random traffic of a seed through the layout until every car is out, `mix`
+// sets the share of cyclists, human drivers or pedestrians first.
+fn run(layout: &str, policy: &str, seed: u64, mix: impl FnOnce(&mut Simulation)) -> Simulation {
+    let mut sim = Simulation::new(seed, policy);
+    sim.set_network(Network::single(Layout::from_arg(layout).unwrap()));
+    mix(&mut sim);
+    sim.start_auto_spawn();
+    for _ in 0..(SECONDS / DT).round() as u64 {
+        sim.tick();
+        if !sim.is_auto_spawning() && sim.cars.is_empty() {
+            break;
+        }
+    }
+    sim
+}
+
+fn assert_clean(sim: &Simulation, run: &str) {
+    assert!(
+        sim.stats.collisions.is_empty(),
+        "{}: {:?}",
+        run,
+        sim.stats.collisions
+    );
+    assert!(
+        sim.stats.pedestrian_collisions.is_empty(),
+        "{}: {:?}",
+        run,
+        sim.stats.pedestrian_collisions
+    );
+    assert_eq!(
+        sim.stats.finished().count(),
+        sim.stats.spawned,
+        "{}: not every car finished",
+        run
+    );
+}
+
+// every policy gets the random traffic through every built in layout without a crash
+#[test]
+fn every_policy_clears_every_layout() {
+    for layout in LAYOUT_NAMES {
+        for policy in POLICY_NAMES {
+            let sim = run(layout, policy, 1, |_| {});
+            assert_clean(&sim, &format!("{} on {}", policy, layout));
+        }
+    }
+}
+
+// seeds where a reservation once booked a car over the slots of one ahead that a
+// cyclist held up
+#[test]
+fn reservation_books_around_cyclists() {
+    for (layout, seed) in [
+        ("layouts/shared_lanes.toml", 1),
+        ("layouts/wide_lanes.toml", 2),
+    ] {
+        let sim = run(layout, "reservation", seed, |sim| sim.cyclists = 30.0);
+        assert_clean(&sim, &format!("{} seed {}", layout, seed));
+    }
+}
+
+// pedestrians coming all the time once held a car at a zebra crossing for good, and every
+// car behind it under `priority`
+#[test]
+fn every_policy_lets_pedestrians_and_cars_across() {
+    for policy in POLICY_NAMES {
+        let sim = run("cross", policy, 1, |sim| sim.pedestrian_rate = 20.0);
+        assert_clean(&sim, &format!("{} with pedestrians", policy));
+    }
+}
+
+// a human driver at the head of a queue once waited for pedestrians at the kerb who
+// waited for it in turn, and under `lights` a car let through behind one held for a
+// human driver kept the next green from coming
+#[test]
+fn human_drivers_and_pedestrians_get_across() {
+    for (policy, humans) in POLICY_NAMES.iter().flat_map(|p| [(p, 20.0), (p, 30.0)]) {
+        let sim = run("cross", policy, 1, |sim| {
+            sim.humans = humans;
+            sim.pedestrian_rate = 20.0;
+        });
+        assert_clean(&sim, &format!("{} with {}% human drivers", policy, humans));
+    }
+}